@@ -1,10 +1,18 @@
 use rend_ox::app::{app, App};
-use rend_ox::mesh::MeshDescriptor;
+use rend_ox::glam::{EulerRot, Quat};
+use rend_ox::mesh::{Aabb, MeshDescriptor, Primitive};
 use rend_ox::nannou::event::Key;
 use rend_ox::nannou_egui::egui::CtxRef;
-use rend_ox::Vec3;
+use rend_ox::{Mat4, Vec3};
 use std::f64::consts::PI;
 
+/// the ball bounces off the walls when its center passes this height
+const FIELD_HALF_HEIGHT: f32 = 13.;
+/// the ball is missed when its center is farther than this from the height of the paddle
+const PADDLE_REACH: f32 = 8.;
+const BALL_SCALE: f32 = 2.;
+const PADDLE_SCALE: Vec3 = Vec3::new(2., 2., 3.);
+
 pub struct Pong {
     pub ball: Option<MeshDescriptor>,
     pub rack: Option<MeshDescriptor>,
@@ -13,6 +21,9 @@ pub struct Pong {
     pub speed: f64,
     pub angle: f64,
     pub ball_pos: Vec3,
+    pub ball_radius: f32,
+    /// box of the paddle mesh, before it is placed
+    pub rack_bounds: Aabb,
 }
 
 impl Pong {
@@ -25,6 +36,8 @@ impl Pong {
             speed: 0.1,
             angle: 0.,
             ball_pos: Vec3::new(0.0, -30.0, 0.0),
+            ball_radius: 0.,
+            rack_bounds: Aabb::default(),
         }
     }
 }
@@ -39,6 +52,19 @@ fn bound_val(val: f32, min: f32, max: f32) -> f32 {
     val
 }
 
+/// position and rotation of a paddle at `height`, `side` being 1 on the right and -1 on the left
+fn paddle_placement(side: f32, height: f32) -> (Vec3, Vec3) {
+    let quarter = std::f32::consts::FRAC_PI_2;
+    (Vec3::new(30. * side, -30., height), Vec3::new(0., quarter * side, quarter))
+}
+
+/// box of a paddle at `height`, placed like `App::draw_at` places its instances
+fn paddle_bounds(rack: &Aabb, side: f32, height: f32) -> Aabb {
+    let (position, rotation) = paddle_placement(side, height);
+    let rotation = Quat::from_euler(EulerRot::XYZ, rotation.x, rotation.y, rotation.z);
+    rack.transform(Mat4::from_scale_rotation_translation(PADDLE_SCALE, rotation, position))
+}
+
 pub fn pong_update(
     nannou: &rend_ox::nannou::App,
     app: &mut App<Pong>,
@@ -70,26 +96,31 @@ pub fn pong_update(
     );
     app.user.ball_pos = app.user.ball_pos + movement;
 
-    if (app.user.ball_pos.x > 26. && (app.user.fst_height - app.user.ball_pos.z).abs() > 8.)
-        || (app.user.ball_pos.x < -26. && (app.user.snd_height - app.user.ball_pos.z).abs() > 8.)
+    let radius = app.user.ball_radius;
+    let fst = paddle_bounds(&app.user.rack_bounds, 1., app.user.fst_height);
+    let snd = paddle_bounds(&app.user.rack_bounds, -1., app.user.snd_height);
+    if (app.user.ball_pos.x + radius > fst.min.x
+        && (app.user.fst_height - app.user.ball_pos.z).abs() > PADDLE_REACH)
+        || (app.user.ball_pos.x - radius < snd.max.x
+            && (app.user.snd_height - app.user.ball_pos.z).abs() > PADDLE_REACH)
     {
         app.user.speed = 0.1;
         app.user.angle = PI;
         app.user.ball_pos = Vec3::new(0.0, -30.0, 0.0);
     }
 
-    if app.user.ball_pos.x > 26. {
+    if app.user.ball_pos.x + radius > fst.min.x {
         app.user.angle = app.user.angle - PI
-            + (((app.user.fst_height - app.user.ball_pos.z) / 8.0) as f64 * PI / 4.0) as f64;
+            + (((app.user.fst_height - app.user.ball_pos.z) / PADDLE_REACH) as f64 * PI / 4.0) as f64;
     }
 
-    if app.user.ball_pos.x < -26. {
+    if app.user.ball_pos.x - radius < snd.max.x {
         app.user.angle = app.user.angle
             - PI
-            - (((app.user.snd_height - app.user.ball_pos.z) / 8.0) as f64 * PI / 4.0) as f64;
+            - (((app.user.snd_height - app.user.ball_pos.z) / PADDLE_REACH) as f64 * PI / 4.0) as f64;
     }
 
-    if app.user.ball_pos.z > 13. || app.user.ball_pos.z < -13. {
+    if app.user.ball_pos.z > FIELD_HALF_HEIGHT || app.user.ball_pos.z < -FIELD_HALF_HEIGHT {
         app.user.angle = (PI * 2.0) - app.user.angle;
     }
 
@@ -99,36 +130,32 @@ pub fn pong_update(
             Vec3::new(0.2, 1., 12.),
             app.user.ball_pos,
             Vec3::new(0., 0., 0.),
-            Vec3::new(2., 2., 2.),
+            Vec3::splat(BALL_SCALE),
         );
     }
     if let Some(mesh) = &app.user.rack {
-        app.draw_at(
-            mesh,
-            Vec3::new(1., 1., 12.),
-            Vec3::new(30., -30., app.user.fst_height),
-            Vec3::new(0., std::f32::consts::PI / 2., std::f32::consts::PI / 2.),
-            Vec3::new(2., 2., 3.),
-        );
-        app.draw_at(
-            mesh,
-            Vec3::new(1., 1., 12.),
-            Vec3::new(-30., -30., app.user.snd_height),
-            Vec3::new(0., -std::f32::consts::PI / 2., std::f32::consts::PI / 2.),
-            Vec3::new(2., 2., 3.),
-        );
+        for (side, height) in [(1., app.user.fst_height), (-1., app.user.snd_height)] {
+            let (position, rotation) = paddle_placement(side, height);
+            app.draw_at(mesh, Vec3::new(1., 1., 12.), position, rotation, PADDLE_SCALE);
+        }
     }
 }
 
 fn pong_app(nannou_app: &rend_ox::nannou::App) -> App<Pong> {
     let mut app = app(nannou_app, Pong::new()).update(pong_update);
 
-    if let Ok(md) = app.load_mesh("./ball.obj") {
+    if let Ok(md) = app.load_primitive(Primitive::Icosphere { radius: 1., subdivisions: 3 }) {
+        if let Ok((_, sphere)) = app.mesh_bounds(&md) {
+            app.user.ball_radius = sphere.transform(Mat4::from_scale(Vec3::splat(BALL_SCALE))).radius;
+        }
         app.user.ball = Some(md);
     } else {
         println!("Error loading ball!")
     }
-    if let Ok(md) = app.load_mesh("./rack.obj") {
+    if let Ok(md) = app.load_primitive(Primitive::Cube { size: Vec3::new(2., 5.68, 0.87) }) {
+        if let Ok((aabb, _)) = app.mesh_bounds(&md) {
+            app.user.rack_bounds = aabb;
+        }
         app.user.rack = Some(md);
     } else {
         println!("Error loading rack!")
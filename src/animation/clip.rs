@@ -0,0 +1,78 @@
+//! Animation clips
+//!
+//! Keyframes of each animated bone, relative to its parent like [`Bone::pose()`]
+
+use std::collections::HashMap;
+
+use glam::Quat;
+
+use crate::mesh::Bone;
+use crate::Vec3;
+
+/// The transform of a bone at a given time
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BoneKeyframe {
+    /// time in seconds since the start of the clip
+    pub time: f32,
+    pub translation: Vec3,
+    pub rotation: Quat,
+}
+
+/// A skeletal animation sequence
+///
+/// Keyframes are indexed by [`Bone::idx()`] and sorted by time,
+/// bones without any keyframe keep their reference pose
+#[derive(Clone, Debug)]
+pub struct AnimationClip {
+    pub name: String,
+    /// length of the clip in seconds
+    pub duration: f32,
+    pub(crate) tracks: HashMap<u32, Vec<BoneKeyframe>>,
+}
+
+impl AnimationClip {
+    pub fn new(name: &str) -> AnimationClip {
+        AnimationClip {
+            name: name.into(),
+            duration: 0.,
+            tracks: HashMap::new(),
+        }
+    }
+
+    /// Add a keyframe to a bone, keeping its keyframes sorted and the clip duration up to date
+    pub fn add_keyframe(&mut self, bone: &Bone, keyframe: BoneKeyframe) {
+        let track = self.tracks.entry(bone.idx()).or_default();
+        let at = track.partition_point(|other| other.time <= keyframe.time);
+        track.insert(at, keyframe);
+        self.duration = self.duration.max(keyframe.time);
+    }
+
+    /// Keyframes of a bone, sorted by time
+    pub fn keyframes(&self, bone: u32) -> &[BoneKeyframe] {
+        match self.tracks.get(&bone) {
+            Some(track) => track,
+            None => &[],
+        }
+    }
+
+    /// Indices of the bones animated by this clip
+    pub fn animated_bones(&self) -> impl Iterator<Item = u32> + '_ {
+        self.tracks.keys().copied()
+    }
+
+    /// Interpolate the transform of a bone at `time`, lerping translations and slerping rotations
+    ///
+    /// times outside of the keyframes hold the first or last one, bones without keyframes give `None`
+    pub fn sample(&self, bone: u32, time: f32) -> Option<(Vec3, Quat)> {
+        let track = self.tracks.get(&bone)?;
+        let next = track.partition_point(|keyframe| keyframe.time <= time);
+        match (next.checked_sub(1).map(|i| track[i]), track.get(next).copied()) {
+            (Some(a), Some(b)) => {
+                let t = (time - a.time) / (b.time - a.time);
+                Some((a.translation.lerp(b.translation, t), a.rotation.slerp(b.rotation, t)))
+            }
+            (Some(keyframe), None) | (None, Some(keyframe)) => Some((keyframe.translation, keyframe.rotation)),
+            (None, None) => None,
+        }
+    }
+}
@@ -0,0 +1,14 @@
+//! Skeletal animation
+//!
+//! Clips store keyframes for the bones of a rigged [`crate::mesh::Mesh`],
+//! they can be loaded separately from the mesh they animate.
+//! An [`AnimationPlayer`] samples and blends them into a [`Pose`], whose palette is drawn with
+//! [`crate::app::App::draw_skinned()`]
+
+mod clip;
+mod player;
+mod pose;
+
+pub use clip::*;
+pub use player::*;
+pub use pose::*;
@@ -0,0 +1,254 @@
+//! Animation playback
+//!
+//! Plays clips over time, blending them by weight and fading between them
+
+use glam::Mat4;
+
+use crate::animation::{AnimationClip, Pose};
+use crate::mesh::Bone;
+
+/// A clip being played, with its own time and weight
+#[derive(Clone, Debug)]
+struct Layer {
+    clip: AnimationClip,
+    time: f32,
+    weight: f32,
+    /// weight the layer fades toward, it is removed once faded out
+    target: f32,
+    /// change of weight per second while fading
+    fade_speed: f32,
+}
+
+impl Layer {
+    fn new(clip: AnimationClip, weight: f32) -> Layer {
+        Layer {
+            clip,
+            time: 0.,
+            weight,
+            target: weight,
+            fade_speed: 0.,
+        }
+    }
+
+    fn fade_to(&mut self, target: f32, duration: f32) {
+        self.target = target;
+        if duration > 0. {
+            self.fade_speed = (target - self.weight).abs() / duration;
+        } else {
+            self.weight = target;
+            self.fade_speed = 0.;
+        }
+    }
+}
+
+/// Plays animation clips on a skeleton
+///
+/// clips are sampled on the cpu, blended by weight, and turned into a bone palette for
+/// [`crate::app::App::draw_skinned()`]:
+/// ```ignore
+/// player.update(dt);
+/// app.draw_skinned(&md, &player.palette(mesh.bones()));
+/// ```
+#[derive(Clone, Debug)]
+pub struct AnimationPlayer {
+    layers: Vec<Layer>,
+    /// playback rate, 1 being real time
+    pub speed: f32,
+    /// restart clips when they reach their end, otherwise hold their last frame
+    pub looping: bool,
+}
+
+impl Default for AnimationPlayer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl AnimationPlayer {
+    pub fn new() -> AnimationPlayer {
+        AnimationPlayer {
+            layers: vec![],
+            speed: 1.,
+            looping: true,
+        }
+    }
+
+    /// Play a single clip from its start, stopping every other one
+    pub fn play(&mut self, clip: &AnimationClip) {
+        self.layers = vec![Layer::new(clip.clone(), 1.)];
+    }
+
+    /// Play a clip alongside the current ones, blended by `weight`
+    ///
+    /// a clip already playing only has its weight changed
+    pub fn blend(&mut self, clip: &AnimationClip, weight: f32) {
+        match self.layers.iter_mut().find(|layer| layer.clip.name == clip.name) {
+            Some(layer) => layer.fade_to(weight, 0.),
+            None => self.layers.push(Layer::new(clip.clone(), weight)),
+        }
+    }
+
+    /// Change the weight of a playing clip, returns false if it isn't playing
+    pub fn set_weight(&mut self, name: &str, weight: f32) -> bool {
+        match self.layers.iter_mut().find(|layer| layer.clip.name == name) {
+            Some(layer) => {
+                layer.fade_to(weight, 0.);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Fade a clip in over `duration` seconds, while fading every other one out
+    pub fn crossfade(&mut self, clip: &AnimationClip, duration: f32) {
+        for layer in &mut self.layers {
+            layer.fade_to(0., duration);
+        }
+        match self.layers.iter_mut().find(|layer| layer.clip.name == clip.name) {
+            Some(layer) => layer.fade_to(1., duration),
+            None => {
+                let mut layer = Layer::new(clip.clone(), 0.);
+                layer.fade_to(1., duration);
+                self.layers.push(layer);
+            }
+        }
+    }
+
+    /// Names of the playing clips with their current weight
+    pub fn playing(&self) -> impl Iterator<Item = (&str, f32)> + '_ {
+        self.layers.iter().map(|layer| (layer.clip.name.as_str(), layer.weight))
+    }
+
+    /// Advance every clip and fade by `dt` seconds
+    pub fn update(&mut self, dt: f32) {
+        for layer in &mut self.layers {
+            layer.time += dt * self.speed;
+            let duration = layer.clip.duration;
+            if self.looping && duration > 0. {
+                layer.time = layer.time.rem_euclid(duration);
+            } else {
+                layer.time = layer.time.clamp(0., duration);
+            }
+            let step = layer.fade_speed * dt;
+            if (layer.target - layer.weight).abs() <= step {
+                layer.weight = layer.target;
+            } else {
+                layer.weight += step.copysign(layer.target - layer.weight);
+            }
+        }
+        self.layers.retain(|layer| layer.weight > 0. || layer.target > 0.);
+    }
+
+    /// Blend the playing clips into a single pose, the reference pose when nothing plays
+    pub fn pose(&self, bones: &[Bone]) -> Pose {
+        let mut pose = Pose::bind(bones);
+        let mut total = 0.;
+        for layer in self.layers.iter().filter(|layer| layer.weight > 0.) {
+            total += layer.weight;
+            let sample = Pose::sample(&layer.clip, bones, layer.time);
+            pose = pose.blend(&sample, layer.weight / total);
+        }
+        pose
+    }
+
+    /// Skinning matrix of each bone for the current pose, see [`Pose::palette()`]
+    pub fn palette(&self, bones: &[Bone]) -> Vec<Mat4> {
+        self.pose(bones).palette(bones)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::Quat;
+
+    use super::*;
+    use crate::animation::BoneKeyframe;
+    use crate::Vec3;
+
+    fn skeleton() -> Vec<Bone> {
+        vec![Bone {
+            idx: 0,
+            name: "root".into(),
+            pose: Mat4::IDENTITY,
+            parent: -1,
+        }]
+    }
+
+    /// a clip moving the root bone through `translations`, one per second
+    fn clip(name: &str, translations: &[Vec3]) -> AnimationClip {
+        let mut clip = AnimationClip::new(name);
+        for (i, translation) in translations.iter().enumerate() {
+            let keyframe = BoneKeyframe {
+                time: i as f32,
+                translation: *translation,
+                rotation: Quat::IDENTITY,
+            };
+            clip.add_keyframe(&skeleton()[0], keyframe);
+        }
+        clip
+    }
+
+    fn translation(player: &AnimationPlayer) -> Vec3 {
+        player.pose(&skeleton()).local(0).unwrap().0
+    }
+
+    fn assert_near(a: Vec3, b: Vec3) {
+        assert!(a.abs_diff_eq(b, 1e-5), "{a} != {b}");
+    }
+
+    #[test]
+    fn blending_is_normalized() {
+        let (a, b) = (clip("a", &[Vec3::X]), clip("b", &[Vec3::Y]));
+        let mut player = AnimationPlayer::new();
+        assert_near(translation(&player), Vec3::ZERO);
+
+        player.blend(&a, 1.);
+        player.blend(&b, 3.);
+        assert_near(translation(&player), Vec3::new(0.25, 0.75, 0.));
+
+        // weights below 1 don't pull toward the reference pose
+        player.set_weight("a", 0.25);
+        player.set_weight("b", 0.25);
+        assert_near(translation(&player), Vec3::new(0.5, 0.5, 0.));
+
+        assert!(!player.set_weight("c", 1.));
+        player.set_weight("b", 0.);
+        assert_near(translation(&player), Vec3::X);
+    }
+
+    #[test]
+    fn crossfade_moves_weights_over_time() {
+        let (a, b) = (clip("a", &[Vec3::X]), clip("b", &[Vec3::Y]));
+        let mut player = AnimationPlayer::new();
+        player.play(&a);
+        player.crossfade(&b, 1.);
+        assert_eq!(player.playing().collect::<Vec<_>>(), [("a", 1.), ("b", 0.)]);
+
+        player.update(0.25);
+        assert_eq!(player.playing().collect::<Vec<_>>(), [("a", 0.75), ("b", 0.25)]);
+        assert_near(translation(&player), Vec3::new(0.75, 0.25, 0.));
+
+        // faded out clips stop playing
+        player.update(1.);
+        assert_eq!(player.playing().collect::<Vec<_>>(), [("b", 1.)]);
+        assert_near(translation(&player), Vec3::Y);
+    }
+
+    #[test]
+    fn clips_loop_or_hold_their_end() {
+        let walk = clip("walk", &[Vec3::ZERO, Vec3::X, Vec3::X * 2.]);
+        let mut player = AnimationPlayer::new();
+        player.play(&walk);
+        player.update(2.5);
+        assert_near(translation(&player), Vec3::X * 0.5);
+
+        player.looping = false;
+        player.play(&walk);
+        player.update(2.5);
+        assert_near(translation(&player), Vec3::X * 2.);
+
+        player.speed = -1.;
+        player.update(5.);
+        assert_near(translation(&player), Vec3::ZERO);
+    }
+}
@@ -0,0 +1,150 @@
+//! Skeleton poses
+//!
+//! The local transform of each bone, built from the reference pose or sampled from clips,
+//! and turned into the bone matrices expected by [`crate::app::App::draw_skinned()`]
+
+use glam::{Mat4, Quat};
+
+use crate::animation::AnimationClip;
+use crate::mesh::Bone;
+use crate::Vec3;
+
+/// The transform of each bone relative to its parent, indexed by [`Bone::idx()`]
+#[derive(Clone, Debug, PartialEq)]
+pub struct Pose {
+    pub(crate) locals: Vec<(Vec3, Quat)>,
+}
+
+fn bone_count(bones: &[Bone]) -> usize {
+    bones.iter().map(|bone| bone.idx() as usize + 1).max().unwrap_or(0)
+}
+
+impl Pose {
+    /// The reference pose of a skeleton
+    pub fn bind(bones: &[Bone]) -> Pose {
+        let mut locals = vec![(Vec3::ZERO, Quat::IDENTITY); bone_count(bones)];
+        for bone in bones {
+            let (_, rotation, translation) = bone.pose().to_scale_rotation_translation();
+            locals[bone.idx() as usize] = (translation, rotation);
+        }
+        Pose { locals }
+    }
+
+    /// Sample a clip at `time` in seconds, bones it does not animate keep their reference pose
+    pub fn sample(clip: &AnimationClip, bones: &[Bone], time: f32) -> Pose {
+        let mut pose = Pose::bind(bones);
+        for bone in bones {
+            if let Some(local) = clip.sample(bone.idx(), time) {
+                pose.locals[bone.idx() as usize] = local;
+            }
+        }
+        pose
+    }
+
+    /// Transform of a bone relative to its parent
+    pub fn local(&self, bone: u32) -> Option<(Vec3, Quat)> {
+        self.locals.get(bone as usize).copied()
+    }
+
+    /// Override the transform of a bone relative to its parent
+    pub fn set_local(&mut self, bone: u32, translation: Vec3, rotation: Quat) {
+        if let Some(local) = self.locals.get_mut(bone as usize) {
+            *local = (translation, rotation);
+        }
+    }
+
+    /// Interpolate toward another pose of the same skeleton, a `weight` of 0 gives `self` and 1 gives `other`
+    pub fn blend(&self, other: &Pose, weight: f32) -> Pose {
+        let locals = self
+            .locals
+            .iter()
+            .zip(&other.locals)
+            .map(|((ta, ra), (tb, rb))| (ta.lerp(*tb, weight), ra.slerp(*rb, weight)))
+            .collect();
+        Pose { locals }
+    }
+
+    /// Model space transform of each bone, indexed by [`Bone::idx()`]
+    pub fn globals(&self, bones: &[Bone]) -> Vec<Mat4> {
+        let mut globals: Vec<Option<Mat4>> = vec![None; self.locals.len()];
+        for bone in bones {
+            self.resolve_global(bones, bone, &mut globals);
+        }
+        globals.iter().map(|global| global.unwrap_or(Mat4::IDENTITY)).collect()
+    }
+
+    fn resolve_global(&self, bones: &[Bone], bone: &Bone, globals: &mut [Option<Mat4>]) -> Mat4 {
+        let idx = bone.idx() as usize;
+        if let Some(global) = globals[idx] {
+            return global;
+        }
+        let (translation, rotation) = self.locals[idx];
+        let local = Mat4::from_rotation_translation(rotation, translation);
+        // a bone claiming itself as an ancestor is treated as a root
+        globals[idx] = Some(local);
+        let parent = bone
+            .parent()
+            .and_then(|parent| bones.iter().find(|other| other.idx() == parent));
+        let global = match parent {
+            Some(parent) => self.resolve_global(bones, parent, globals) * local,
+            None => local,
+        };
+        globals[idx] = Some(global);
+        global
+    }
+
+    /// Skinning matrix of each bone, moving vertices from the reference pose to this one
+    ///
+    /// this is the palette expected by [`crate::app::App::draw_skinned()`]
+    pub fn palette(&self, bones: &[Bone]) -> Vec<Mat4> {
+        let bind = Pose::bind(bones).globals(bones);
+        self.globals(bones)
+            .iter()
+            .zip(bind)
+            .map(|(global, bind)| *global * bind.inverse())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bone(idx: u32, parent: i32, pose: Mat4) -> Bone {
+        Bone {
+            idx,
+            name: format!("bone{idx}"),
+            pose,
+            parent,
+        }
+    }
+
+    #[test]
+    fn blend_interpolates_each_bone() {
+        let bones = [bone(0, -1, Mat4::IDENTITY)];
+        let from = Pose::bind(&bones);
+        let mut to = from.clone();
+        to.set_local(0, Vec3::X * 2., Quat::from_rotation_z(std::f32::consts::FRAC_PI_2));
+
+        let (translation, rotation) = from.blend(&to, 0.5).local(0).unwrap();
+        assert!(translation.abs_diff_eq(Vec3::X, 1e-5));
+        assert!(rotation.abs_diff_eq(Quat::from_rotation_z(std::f32::consts::FRAC_PI_4), 1e-5));
+        assert_eq!(from.blend(&to, 0.), from);
+        assert_eq!(from.blend(&to, 1.), to);
+    }
+
+    #[test]
+    fn palette_moves_children_with_their_parents() {
+        let bones = [
+            bone(0, -1, Mat4::IDENTITY),
+            bone(1, 0, Mat4::from_translation(Vec3::Y)),
+        ];
+        let mut pose = Pose::bind(&bones);
+        assert!(pose.palette(&bones).iter().all(|matrix| matrix.abs_diff_eq(Mat4::IDENTITY, 1e-5)));
+
+        pose.set_local(0, Vec3::X, Quat::IDENTITY);
+        let palette = pose.palette(&bones);
+        let tip = palette[1].transform_point3(Vec3::Y);
+        assert!(tip.abs_diff_eq(Vec3::new(1., 1., 0.), 1e-5));
+    }
+}
@@ -10,14 +10,16 @@ use std::ops::Deref;
 use crate::Vec3;
 use glam::{EulerRot, Mat4, Quat};
 use nannou;
+use nannou::wgpu;
 use nannou_egui::Egui;
 use nannou_egui::egui::CtxRef;
 
 use crate::camera_controller::key_pressed;
 use crate::graphics::{Graphics, MaterialSlot, ShaderSlot};
 use crate::material::{MaterialDescriptor};
-use crate::mesh::MeshDescriptor;
+use crate::mesh::{Aabb, BoundingSphere, Mesh, MeshDescriptor, Primitive};
 use crate::error::RendError;
+use crate::frustum::CullingStats;
 use crate::process::{event, update, view};
 
 pub type RendoxAppFn<T> = fn(_: &nannou::App) -> App<T>;
@@ -47,11 +49,14 @@ pub(crate) fn vertices_as_bytes_copy(data: &Vec<Vec3>) -> Vec<u8> {
     final_bytes
 }
 
-pub(crate) fn indices_as_bytes_copy(data: &Vec<u16>) -> Vec<u8> {
+/// copy indices as bytes, narrowed to 16 bits when `format` is `Uint16`
+pub(crate) fn indices_as_bytes_copy(data: &[u32], format: wgpu::IndexFormat) -> Vec<u8> {
     let mut final_bytes: Vec<u8> = vec![];
     for elem in data {
-        final_bytes.push(*elem as u8);
-        final_bytes.push((*elem >> 8) as u8);
+        match format {
+            wgpu::IndexFormat::Uint16 => final_bytes.extend((*elem as u16).to_le_bytes()),
+            wgpu::IndexFormat::Uint32 => final_bytes.extend(elem.to_le_bytes()),
+        }
     }
     final_bytes
 }
@@ -199,6 +204,88 @@ impl<T> App<T> {
         return Err(Box::new(RendError::new("Graphics module borrowed")));
     }
 
+    /// load a mesh from obj data in memory and return a unique MeshDescriptor
+    ///
+    /// `name` identifies the mesh, loading the same name twice gives the same mesh
+    pub fn load_mesh_from_bytes(&mut self, name: &str, bytes: &[u8]) -> Result<MeshDescriptor, Box<dyn std::error::Error>> {
+        if let Ok(mut g) = self.graphics.try_borrow_mut() {
+            return g.load_mesh_from_bytes(name, bytes);
+        }
+        Err(Box::new(RendError::new("Graphics module borrowed")))
+    }
+
+    /// load a mesh built at runtime, such as one from [`crate::mesh::MeshBuilder`], and return a unique MeshDescriptor
+    ///
+    /// `name` identifies the mesh, loading the same name twice gives the first mesh
+    pub fn load_mesh_data(&mut self, name: &str, mesh: Mesh) -> Result<MeshDescriptor, Box<dyn std::error::Error>> {
+        if let Ok(mut g) = self.graphics.try_borrow_mut() {
+            return Ok(g.load_mesh_data(name, mesh));
+        }
+        Err(Box::new(RendError::new("Graphics module borrowed")))
+    }
+
+    /// generate a primitive shape and return a unique MeshDescriptor
+    ///
+    /// loading the same shape with the same parameters twice gives the same mesh
+    /// ```ignore
+    /// let ball = app.load_primitive(Primitive::Icosphere { radius: 1., subdivisions: 3 })?;
+    /// ```
+    pub fn load_primitive(&mut self, primitive: Primitive) -> Result<MeshDescriptor, Box<dyn std::error::Error>> {
+        if let Ok(mut g) = self.graphics.try_borrow_mut() {
+            return Ok(g.load_primitive(primitive));
+        }
+        Err(Box::new(RendError::new("Graphics module borrowed")))
+    }
+
+    /// load a mesh from a file and return a MeshDescriptor for each of its objects and groups
+    ///
+    /// each of them can be drawn and given a material independently
+    pub fn load_submeshes(&mut self, path: &str) -> Result<Vec<MeshDescriptor>, Box<dyn std::error::Error>> {
+        if let Ok(mut g) = self.graphics.try_borrow_mut() {
+            return g.load_submeshes(path);
+        }
+        Err(Box::new(RendError::new("Graphics module borrowed")))
+    }
+
+    /// export meshes placed by their transforms as a binary glTF file
+    ///
+    /// each mesh is written with the materials bound to its descriptor, a descriptor placed several times is written once
+    pub fn export_glb(&self, path: &str, nodes: &[(MeshDescriptor, Mat4)]) -> Result<(), Box<dyn std::error::Error>> {
+        if let Ok(g) = self.graphics.try_borrow() {
+            return g.export_glb(path, nodes);
+        }
+        Err(Box::new(RendError::new("Graphics module borrowed")))
+    }
+
+    /// the box and sphere around a loaded mesh, in model space
+    ///
+    /// submeshes give the bounds of their whole mesh
+    pub fn mesh_bounds(&self, md: &MeshDescriptor) -> Result<(Aabb, BoundingSphere), Box<dyn std::error::Error>> {
+        if let Ok(g) = self.graphics.try_borrow() {
+            if let Some(bounds) = g.mesh_bounds(md) {
+                return Ok(bounds);
+            }
+            return Err(Box::new(RendError::new(&format!("Mesh {} is not loaded", md.name))));
+        }
+        Err(Box::new(RendError::new("Graphics module borrowed")))
+    }
+
+    /// the world box and sphere of each instance of a mesh drawn so far this frame, in the order they were drawn
+    pub fn queued_bounds(&self, md: &MeshDescriptor) -> Result<Vec<(Aabb, BoundingSphere)>, Box<dyn std::error::Error>> {
+        if let Ok(g) = self.graphics.try_borrow() {
+            return Ok(g.instance_bounds(md));
+        }
+        Err(Box::new(RendError::new("Graphics module borrowed")))
+    }
+
+    /// how many of the instances drawn during the last frame were culled by the camera frustum
+    pub fn culling_stats(&self) -> Result<CullingStats, Box<dyn std::error::Error>> {
+        if let Ok(g) = self.graphics.try_borrow() {
+            return Ok(g.culling_stats);
+        }
+        Err(Box::new(RendError::new("Graphics module borrowed")))
+    }
+
     /// draw a mesh with no transforms
     pub fn draw(&self, md: &MeshDescriptor, color: Vec3) -> bool {
         if let Ok(mut g) = self.graphics.try_borrow_mut() {
@@ -256,4 +343,31 @@ impl<T> App<T> {
         println!("Rendox: failed instanced draw call of {}", md.name);
         false
     }
+
+    /// draw a rigged mesh deformed by a pose, with no transforms
+    ///
+    /// `pose` holds the skinning matrix of each bone, indexed by [`crate::mesh::Bone::idx()`]:
+    /// the transform from the bind pose to the animated pose, in model space
+    pub fn draw_skinned(&self, md: &MeshDescriptor, pose: &[Mat4]) -> bool {
+        self.draw_skinned_at(md, Vec3::new(1., 1., 1.), Vec3::ZERO, Vec3::ZERO, Vec3::ONE, pose)
+    }
+
+    /// draw a rigged mesh deformed by a pose, at a given position rotation and scale, with given instance color
+    pub fn draw_skinned_at(
+        &self,
+        md: &MeshDescriptor,
+        color: Vec3,
+        pos: Vec3,
+        rot: Vec3,
+        scale: Vec3,
+        pose: &[Mat4],
+    ) -> bool {
+        if let Ok(mut g) = self.graphics.try_borrow_mut() {
+            let transform = Mat4::from_scale_rotation_translation(scale, Quat::from_euler(EulerRot::XYZ, rot.x, rot.y, rot.z), pos);
+            g.skinned_queue.push((md.clone(), color, transform, pose.to_vec()));
+            return true;
+        }
+        println!("Rendox: failed skinned draw call of {}", md.name);
+        false
+    }
 }
@@ -27,3 +27,80 @@ impl std::error::Error for RendError {
         &self.details
     }
 }
+
+/// The reason a mesh file couldn't be parsed
+#[derive(Debug)]
+pub enum MeshParseErrorKind {
+    /// The file couldn't be opened or read
+    Io(std::io::Error),
+    /// A value isn't a valid number
+    InvalidNumber,
+    /// An index refers to an element that doesn't exist
+    IndexOutOfRange,
+    /// A statement lacks some of its values
+    MissingValue,
+    /// The statement isn't supported by the parser
+    UnsupportedStatement,
+    /// A part of the file relies on a feature the parser doesn't support, described by the message
+    UnsupportedFeature(String),
+    /// The file extension doesn't match any supported format
+    UnsupportedFormat,
+    /// The data doesn't match its checksum
+    InvalidChecksum,
+    /// The glTF document or one of its buffers is invalid
+    Gltf(gltf::Error),
+}
+
+impl std::fmt::Display for MeshParseErrorKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            MeshParseErrorKind::Io(why) => write!(f, "{}", why),
+            MeshParseErrorKind::InvalidNumber => write!(f, "invalid number"),
+            MeshParseErrorKind::IndexOutOfRange => write!(f, "index out of range"),
+            MeshParseErrorKind::MissingValue => write!(f, "missing value"),
+            MeshParseErrorKind::UnsupportedStatement => write!(f, "unsupported statement"),
+            MeshParseErrorKind::UnsupportedFeature(feature) => write!(f, "unsupported {}", feature),
+            MeshParseErrorKind::UnsupportedFormat => write!(f, "unsupported format"),
+            MeshParseErrorKind::InvalidChecksum => write!(f, "invalid checksum"),
+            MeshParseErrorKind::Gltf(why) => write!(f, "glTF: {}", why),
+        }
+    }
+}
+
+/// An error found while parsing a mesh file
+///
+/// `line` and `column` start at 1, they are 0 when the error isn't tied to a position in the file
+#[derive(Debug)]
+pub struct MeshParseError {
+    pub file: String,
+    pub line: usize,
+    pub column: usize,
+    pub kind: MeshParseErrorKind,
+}
+
+impl MeshParseError {
+    pub fn new(file: &str, line: usize, column: usize, kind: MeshParseErrorKind) -> MeshParseError {
+        MeshParseError {
+            file: file.to_string(),
+            line,
+            column,
+            kind,
+        }
+    }
+}
+
+impl std::fmt::Display for MeshParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}:{}:{}: {}", self.file, self.line, self.column, self.kind)
+    }
+}
+
+impl std::error::Error for MeshParseError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match &self.kind {
+            MeshParseErrorKind::Io(why) => Some(why),
+            MeshParseErrorKind::Gltf(why) => Some(why),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,132 @@
+//! Camera frustum
+//!
+//! The volume seen by the camera, used to skip the instances lying outside of it before they are uploaded
+
+use glam::{Mat4, Vec4};
+
+use crate::mesh::{Aabb, BoundingSphere};
+use crate::Vec3;
+
+/// The six planes bounding what a projection shows, their normals pointing inside
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Frustum {
+    /// left, right, bottom, top, near and far planes, as a normal and a distance to the origin
+    planes: [Vec4; 6],
+}
+
+impl Frustum {
+    /// Extract the planes of a world to clip space matrix, such as `proj * view * world`
+    ///
+    /// the near plane follows the OpenGL depth range, which holds the one wgpu clips to
+    pub fn from_matrix(matrix: Mat4) -> Frustum {
+        let [x, y, z, w] = [0, 1, 2, 3].map(|i| matrix.row(i));
+        let planes = [w + x, w - x, w + y, w - y, w + z, w - z].map(|plane| {
+            let length = plane.truncate().length();
+            if length > 0. { plane / length } else { plane }
+        });
+        Frustum { planes }
+    }
+
+    pub fn contains_point(&self, point: Vec3) -> bool {
+        self.planes.iter().all(|plane| plane.dot(point.extend(1.)) >= 0.)
+    }
+
+    /// Whether a sphere lies at least partly inside the frustum
+    ///
+    /// spheres near the corners may be kept while being outside, never the other way around
+    pub fn intersects_sphere(&self, sphere: &BoundingSphere) -> bool {
+        let center = sphere.center.extend(1.);
+        self.planes.iter().all(|plane| plane.dot(center) >= -sphere.radius)
+    }
+
+    /// Whether a box lies at least partly inside the frustum, tested against its corner farthest along each plane
+    pub fn intersects_aabb(&self, aabb: &Aabb) -> bool {
+        self.planes.iter().all(|plane| {
+            let normal = plane.truncate();
+            let corner = Vec3::select(normal.cmpge(Vec3::ZERO), aabb.max, aabb.min);
+            plane.dot(corner.extend(1.)) >= 0.
+        })
+    }
+}
+
+/// Instances culled by the camera frustum during the last frame, for profiling
+///
+/// skinned meshes are deformed on the gpu, so they are always drawn and left out of these counts
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CullingStats {
+    /// instances queued for drawing
+    pub queued: u32,
+    /// instances at least partly inside the frustum, uploaded and drawn
+    pub visible: u32,
+    /// meshes whose every instance was culled, none of their buffers being uploaded
+    pub skipped_meshes: u32,
+}
+
+impl CullingStats {
+    /// instances outside the frustum, neither uploaded nor drawn
+    pub fn culled(&self) -> u32 {
+        self.queued - self.visible
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A camera at `(0, 0, 10)` looking down `-Z`, seeing 10 units on each side of the origin
+    fn frustum() -> Frustum {
+        let projection = Mat4::perspective_rh(std::f32::consts::FRAC_PI_2, 1., 1., 100.);
+        let view = Mat4::look_at_rh(Vec3::new(0., 0., 10.), Vec3::ZERO, Vec3::Y);
+        Frustum::from_matrix(projection * view)
+    }
+
+    #[test]
+    fn planes_are_normalized() {
+        let frustum = frustum();
+        assert!(frustum.planes.iter().all(|plane| (plane.truncate().length() - 1.).abs() < 1e-5));
+        // the side planes lie at 45 degrees, the origin being as far from each of them
+        let origin = Vec3::ZERO.extend(1.);
+        for plane in &frustum.planes[..4] {
+            assert!((plane.dot(origin) - 10. / 2f32.sqrt()).abs() < 1e-4);
+        }
+        assert!((frustum.planes[5].dot(origin) - 90.).abs() < 1e-3);
+    }
+
+    #[test]
+    fn points_are_inside_or_outside() {
+        let frustum = frustum();
+        assert!(frustum.contains_point(Vec3::ZERO));
+        assert!(frustum.contains_point(Vec3::new(9., -9., 0.)));
+        assert!(!frustum.contains_point(Vec3::new(11., 0., 0.)));
+        assert!(!frustum.contains_point(Vec3::new(0., 0., 20.)));
+        assert!(!frustum.contains_point(Vec3::new(0., 0., -100.)));
+    }
+
+    #[test]
+    fn spheres_are_culled_outside() {
+        let frustum = frustum();
+        let sphere = |x: f32, y: f32, z: f32| frustum.intersects_sphere(&BoundingSphere::new(Vec3::new(x, y, z), 1.));
+        assert!(sphere(0., 0., 0.));
+        // centered outside, but reaching across the side plane
+        assert!(sphere(10.5, 0., 0.));
+        assert!(sphere(0., -10.5, 0.));
+        assert!(!sphere(30., 0., 0.));
+        assert!(!sphere(0., 12., 0.));
+        assert!(!sphere(0., 0., -200.));
+        assert!(!sphere(0., 0., 20.));
+    }
+
+    #[test]
+    fn boxes_are_culled_outside() {
+        let frustum = frustum();
+        let aabb = |min: Vec3, max: Vec3| frustum.intersects_aabb(&Aabb::new(min, max));
+        assert!(aabb(Vec3::splat(-1.), Vec3::ONE));
+        // straddling the right plane, or holding the whole frustum slice
+        assert!(aabb(Vec3::new(9., -1., -1.), Vec3::new(12., 1., 1.)));
+        assert!(aabb(Vec3::splat(-50.), Vec3::splat(50.)));
+        assert!(!aabb(Vec3::new(20., -1., -1.), Vec3::new(22., 1., 1.)));
+        assert!(!aabb(Vec3::new(-1., -1., -300.), Vec3::new(1., 1., -200.)));
+        // wide enough to cross every side plane, but behind the camera
+        assert!(!aabb(Vec3::new(-50., -50., 12.), Vec3::new(50., 50., 14.)));
+    }
+}
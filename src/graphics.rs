@@ -5,9 +5,11 @@
 use std::collections::HashMap;
 use std::mem::{size_of};
 
-use crate::app::{indices_as_bytes_copy, vertices_as_bytes_copy};
+use crate::app::{indices_as_bytes_copy, matrices_as_bytes_copy, vertices_as_bytes_copy};
 use crate::mesh::Mesh;
-use crate::mesh::MeshDescriptor;
+use crate::mesh::{Aabb, BoundingSphere, GltfScene, MeshDescriptor, MeshPart, Primitive};
+use crate::error::RendError;
+use crate::frustum::CullingStats;
 use crate::uniforms::Uniforms;
 use crate::Mat4;
 use crate::Vec3;
@@ -21,20 +23,46 @@ pub type ShaderSlot = usize;
 pub type MaterialSlot = usize;
 pub type MeshSlot = usize;
 
+/// A skinned mesh queued for drawing, with its instance color, transform and bone matrices
+pub(crate) type SkinnedDraw = (MeshDescriptor, Vec3, Mat4, Vec<Mat4>);
+
+/// gpu resources used to draw meshes deformed by their skeleton
+pub(crate) struct Skinning {
+    bone_layout: wgpu::BindGroupLayout,
+    pipeline_layout: wgpu::PipelineLayout,
+    vs_mod: wgpu::ShaderModule,
+    pub render_pipelines: HashMap<ShaderSlot, wgpu::RenderPipeline>,
+}
+
+/// uniform and depth buffers shared by every draw call
+pub(crate) struct RenderBuffers {
+    pub uniform_buffer: wgpu::Buffer,
+    pub uniform_bind_group: wgpu::BindGroup,
+    pub depth_texture: wgpu::Texture,
+    pub depth_texture_view: wgpu::TextureView,
+}
+
 pub(crate) struct Graphics {
     pub uniform_buffer: wgpu::Buffer,
     pub uniform_bind_group: wgpu::BindGroup,
     pub depth_texture: wgpu::Texture,
     pub depth_texture_view: wgpu::TextureView,
     pub(crate) meshes:      HashMap<MeshSlot    , Mesh>,
+    mesh_parts:             HashMap<MeshSlot    , Vec<MeshPart>>,
     pub(crate) materials:   HashMap<MaterialSlot, Material>,
     pub material_layout:    Option<wgpu::BindGroupLayout>,
     pub material_sources:   HashMap<MaterialSlot, MaterialDescriptor>,
     pub(crate) shaders:     HashMap<ShaderSlot  , wgpu::ShaderModule>,
     pub shader_sources:     HashMap<ShaderSlot  , wgpu::ShaderModuleDescriptor<'static>>,
     pub render_pipelines:   HashMap<ShaderSlot  , wgpu::RenderPipeline>,
+    /// pipelines drawing point clouds, one per shader like `render_pipelines`
+    pub point_pipelines:    HashMap<ShaderSlot  , wgpu::RenderPipeline>,
     pipeline_layout: wgpu::PipelineLayout,
     pub(crate) draw_queue:  HashMap<MeshDescriptor  , (Vec<Vec3>, Vec<Mat4>)>,
+    pub(crate) skinned_queue: Vec<SkinnedDraw>,
+    /// instances culled while drawing the last frame
+    pub(crate) culling_stats: CullingStats,
+    pub(crate) skinning: Skinning,
     default_material: ShaderSlot,
     vs_mod: wgpu::ShaderModule,
     msaa: u32,
@@ -84,6 +112,26 @@ fn create_pipeline_layout(
     device.create_pipeline_layout(&desc)
 }
 
+fn create_bone_bind_group_layout(device: &wgpu::Device) -> wgpu::BindGroupLayout {
+    wgpu::BindGroupLayoutBuilder::new()
+        .storage_buffer(wgpu::ShaderStages::VERTEX, false, true)
+        .build(device)
+}
+
+fn create_skinned_pipeline_layout(
+    device: &wgpu::Device,
+    uniform_bind_group_layout: &wgpu::BindGroupLayout,
+    mat_bind_group_layout: &wgpu::BindGroupLayout,
+    bone_bind_group_layout: &wgpu::BindGroupLayout,
+) -> wgpu::PipelineLayout {
+    let desc = wgpu::PipelineLayoutDescriptor {
+        label: None,
+        bind_group_layouts: &[uniform_bind_group_layout, mat_bind_group_layout, bone_bind_group_layout],
+        push_constant_ranges: &[],
+    };
+    device.create_pipeline_layout(&desc)
+}
+
 fn create_render_pipeline(
     device: &wgpu::Device,
     layout: &wgpu::PipelineLayout,
@@ -93,6 +141,52 @@ fn create_render_pipeline(
     depth_format: wgpu::TextureFormat,
     sample_count: u32,
 ) -> wgpu::RenderPipeline {
+    render_pipeline_builder(layout, vs_mod, fs_mod, dst_format, depth_format, sample_count)
+        .build(device)
+}
+
+// same as `create_render_pipeline`, drawing each index as a point
+fn create_point_render_pipeline(
+    device: &wgpu::Device,
+    layout: &wgpu::PipelineLayout,
+    vs_mod: &wgpu::ShaderModule,
+    fs_mod: &wgpu::ShaderModule,
+    dst_format: wgpu::TextureFormat,
+    depth_format: wgpu::TextureFormat,
+    sample_count: u32,
+) -> wgpu::RenderPipeline {
+    render_pipeline_builder(layout, vs_mod, fs_mod, dst_format, depth_format, sample_count)
+        .primitive_topology(wgpu::PrimitiveTopology::PointList)
+        .build(device)
+}
+
+/// joints and weights of a vertex, interleaved in the skin buffer
+const SKIN_ATTRIBUTES: [wgpu::VertexAttribute; 2] = wgpu::vertex_attr_array![10 => Uint32x4, 11 => Float32x4];
+
+// same as `create_render_pipeline`, followed by the joints and weights of each vertex
+// interleaved in a single buffer, keeping the pipeline within the default limit of 8 vertex buffers
+fn create_skinned_render_pipeline(
+    device: &wgpu::Device,
+    layout: &wgpu::PipelineLayout,
+    vs_mod: &wgpu::ShaderModule,
+    fs_mod: &wgpu::ShaderModule,
+    dst_format: wgpu::TextureFormat,
+    depth_format: wgpu::TextureFormat,
+    sample_count: u32,
+) -> wgpu::RenderPipeline {
+    render_pipeline_builder(layout, vs_mod, fs_mod, dst_format, depth_format, sample_count)
+        .add_vertex_buffer::<[u32; 8]>(&SKIN_ATTRIBUTES)
+        .build(device)
+}
+
+fn render_pipeline_builder<'a>(
+    layout: &'a wgpu::PipelineLayout,
+    vs_mod: &'a wgpu::ShaderModule,
+    fs_mod: &'a wgpu::ShaderModule,
+    dst_format: wgpu::TextureFormat,
+    depth_format: wgpu::TextureFormat,
+    sample_count: u32,
+) -> wgpu::RenderPipelineBuilder<'a> {
     wgpu::RenderPipelineBuilder::from_layout(layout, vs_mod)
         .fragment_shader(&fs_mod)
         .color_format(dst_format)
@@ -101,6 +195,8 @@ fn create_render_pipeline(
         .add_vertex_buffer::<glam::Vec3>(&wgpu::vertex_attr_array![0 => Float32x3])
         .add_vertex_buffer::<glam::Vec3>(&wgpu::vertex_attr_array![1 => Float32x3])
         .add_vertex_buffer::<glam::Vec3>(&wgpu::vertex_attr_array![2 => Float32x3])
+        .add_vertex_buffer::<glam::Vec4>(&wgpu::vertex_attr_array![3 => Float32x4])
+        .add_vertex_buffer::<glam::Vec4>(&wgpu::vertex_attr_array![4 => Float32x4])
         // instance matrix split into 4 vec4
         .add_instance_buffer::<glam::Vec3>(&wgpu::vertex_attr_array![9 => Float32x3])
         .add_instance_buffer::<glam::Mat4>(&[
@@ -130,35 +226,37 @@ fn create_render_pipeline(
         ])
         .depth_format(depth_format)
         .sample_count(sample_count)
-        .build(device)
 }
 
 impl Graphics {
     pub(crate) fn new(
-        uniform_buffer: wgpu::Buffer,
-        uniform_bind_group: wgpu::BindGroup,
-        depth_texture: wgpu::Texture,
-        depth_texture_view: wgpu::TextureView,
+        buffers: RenderBuffers,
         pipeline_layout: wgpu::PipelineLayout,
         material_layout: Option<wgpu::BindGroupLayout>,
         default_material: ShaderSlot,
         vs_mod: wgpu::ShaderModule,
+        skinning: Skinning,
         msaa: u32,
     ) -> Graphics {
         Graphics {
-            uniform_buffer,
-            uniform_bind_group,
-            depth_texture,
-            depth_texture_view,
+            uniform_buffer: buffers.uniform_buffer,
+            uniform_bind_group: buffers.uniform_bind_group,
+            depth_texture: buffers.depth_texture,
+            depth_texture_view: buffers.depth_texture_view,
             meshes: HashMap::new(),
+            mesh_parts: HashMap::new(),
             materials: HashMap::new(),
             material_layout,
             material_sources: HashMap::new(),
             shaders: HashMap::new(),
             shader_sources: HashMap::new(),
             render_pipelines: HashMap::new(),
+            point_pipelines: HashMap::new(),
             pipeline_layout,
             draw_queue: HashMap::new(),
+            culling_stats: CullingStats::default(),
+            skinned_queue: vec![],
+            skinning,
             default_material,
             vs_mod,
             msaa,
@@ -191,15 +289,32 @@ impl Graphics {
 
         let pipeline_layout = create_pipeline_layout(device, &uniform_bind_group_layout, &material_bind_group_layout);
 
-        let mut graphics = Graphics::new(
+        let bone_bind_group_layout = create_bone_bind_group_layout(device);
+        let skinning = Skinning {
+            pipeline_layout: create_skinned_pipeline_layout(
+                device,
+                &uniform_bind_group_layout,
+                &material_bind_group_layout,
+                &bone_bind_group_layout,
+            ),
+            bone_layout: bone_bind_group_layout,
+            vs_mod: device.create_shader_module(&wgpu::include_wgsl!("./shaders/vs_skinned.wgsl")),
+            render_pipelines: HashMap::new(),
+        };
+
+        let buffers = RenderBuffers {
             uniform_buffer,
             uniform_bind_group,
             depth_texture,
             depth_texture_view,
+        };
+        let mut graphics = Graphics::new(
+            buffers,
             pipeline_layout,
             Some(material_bind_group_layout),
             0,
             vs_mod,
+            skinning,
             msaa_samples,
         );
 
@@ -272,6 +387,12 @@ impl Graphics {
                         *idx,
                         self.create_render_pipeline_for_shader(device, &fs_mod),
                     );
+                    self.point_pipelines.insert(
+                        *idx,
+                        self.create_point_render_pipeline_for_shader(device, &fs_mod),
+                    );
+                    let skinned_pipeline = self.create_skinned_render_pipeline_for_shader(device, &fs_mod);
+                    self.skinning.render_pipelines.insert(*idx, skinned_pipeline);
                     self.shaders.insert(*idx, fs_mod);
                 }
             }
@@ -281,26 +402,169 @@ impl Graphics {
     pub(crate) fn bind_material_to_mesh(&self, md: &mut MeshDescriptor, material: &MaterialSlot) -> bool {
         if self.material_sources.contains_key(material) {
             md.material = *material;
+            for part in &mut md.parts {
+                part.material = *material;
+            }
             return true;
         }
         false
     }
 
     /// Entirely load mesh data into cpu memory
+    ///
+    /// materials found alongside the mesh are loaded and bound to the faces using them.
+    /// The parsed mesh is cached in a .rmesh file next to it, used until the mesh file is modified
     pub(crate) fn load_mesh(&mut self, path: &str) -> Result<MeshDescriptor, Box<dyn std::error::Error>> {
-        for (idx, mesh) in &self.meshes {
-            if mesh.path == path {
-                return Ok(MeshDescriptor::new(*idx, path, self.default_material));
-            }
+        if let Some(md) = self.find_mesh(path) {
+            return Ok(md);
         }
-        return match Mesh::from_obj(path) {
-            Ok(mesh) => {
-                let idx = self.meshes.len() as MeshSlot;
-                self.meshes.insert(idx, mesh);
-                Ok(MeshDescriptor::new(idx, path, self.default_material))
-            }
-            Err(e) => Err(e)
+        Ok(self.insert_mesh(Mesh::from_file_cached(path)?, path))
+    }
+
+    /// Load mesh data from obj data in memory, `name` identifies it like a path would
+    pub(crate) fn load_mesh_from_bytes(&mut self, name: &str, bytes: &[u8]) -> Result<MeshDescriptor, Box<dyn std::error::Error>> {
+        if let Some(md) = self.find_mesh(name) {
+            return Ok(md);
+        }
+        Ok(self.insert_mesh(Mesh::from_obj_bytes(bytes, name)?, name))
+    }
+
+    /// Load a mesh built at runtime, `name` identifies it like a path would
+    pub(crate) fn load_mesh_data(&mut self, name: &str, mut mesh: Mesh) -> MeshDescriptor {
+        if let Some(md) = self.find_mesh(name) {
+            return md;
+        }
+        mesh.path = name.into();
+        self.insert_mesh(mesh, name)
+    }
+
+    /// generate a primitive, named after its parameters so that identical shapes share their mesh
+    pub(crate) fn load_primitive(&mut self, primitive: Primitive) -> MeshDescriptor {
+        let name = format!("primitive:{:?}", primitive);
+        if let Some(md) = self.find_mesh(&name) {
+            return md;
+        }
+        self.load_mesh_data(&name, primitive.mesh())
+    }
+
+    /// the model space bounds of the mesh of a descriptor, the whole mesh for submeshes
+    pub(crate) fn mesh_bounds(&self, md: &MeshDescriptor) -> Option<(Aabb, BoundingSphere)> {
+        let mesh = self.meshes.get(&md.idx)?;
+        Some((mesh.aabb(), mesh.bounding_sphere()))
+    }
+
+    /// the world bounds of each instance of a descriptor queued for this frame, in the order they were drawn
+    pub(crate) fn instance_bounds(&self, md: &MeshDescriptor) -> Vec<(Aabb, BoundingSphere)> {
+        let (Some((aabb, sphere)), Some((_, instances))) = (self.mesh_bounds(md), self.draw_queue.get(md)) else {
+            return vec![];
         };
+        instances.iter().map(|&instance| (aabb.transform(instance), sphere.transform(instance))).collect()
+    }
+
+    fn find_mesh(&self, path: &str) -> Option<MeshDescriptor> {
+        let (idx, _) = self.meshes.iter().find(|(_, mesh)| mesh.path == path)?;
+        Some(self.mesh_descriptor(*idx, path))
+    }
+
+    fn insert_mesh(&mut self, mut mesh: Mesh, path: &str) -> MeshDescriptor {
+        if mesh.tangents.len() != mesh.vertices.len() {
+            mesh.generate_tangents();
+        }
+        let idx = self.meshes.len() as MeshSlot;
+        let parts = self.load_mesh_materials(&mesh);
+        self.mesh_parts.insert(idx, parts);
+        self.meshes.insert(idx, mesh);
+        self.mesh_descriptor(idx, path)
+    }
+
+    /// Load a mesh and give a descriptor for each of its objects and groups
+    ///
+    /// a mesh without any group gives a single descriptor of the whole mesh
+    pub(crate) fn load_submeshes(&mut self, path: &str) -> Result<Vec<MeshDescriptor>, Box<dyn std::error::Error>> {
+        let md = self.load_mesh(path)?;
+        let mesh = &self.meshes[&md.idx];
+        if mesh.groups.is_empty() {
+            return Ok(vec![md]);
+        }
+        let index_count = mesh.faces.len();
+        let submeshes = mesh.groups
+            .iter()
+            .enumerate()
+            .map(|(i, (start, name))| {
+                let end = match mesh.groups.get(i + 1) {
+                    Some((next, _)) => *next,
+                    None => index_count as u32,
+                };
+                md.submesh(name, *start, end, index_count)
+            })
+            .collect();
+        Ok(submeshes)
+    }
+
+    /// load the materials of a mesh, and split it into parts drawn with each of them
+    fn load_mesh_materials(&mut self, mesh: &Mesh) -> Vec<MeshPart> {
+        let mut slots: HashMap<&str, MaterialSlot> = HashMap::new();
+        let mut parts = vec![];
+        for (i, (start, name)) in mesh.materials.iter().enumerate() {
+            let end = match mesh.materials.get(i + 1) {
+                Some((next, _)) => *next,
+                None => mesh.faces.len() as u32,
+            };
+            let source = name
+                .as_deref()
+                .and_then(|name| mesh.material_sources.get_key_value(name));
+            let material = match source {
+                Some((name, source)) => *slots
+                    .entry(name.as_str())
+                    .or_insert_with(|| self.load_material(source.clone())),
+                None => self.default_material,
+            };
+            parts.push(MeshPart { start: *start, end, material });
+        }
+        parts
+    }
+
+    /// Write meshes placed by their transforms to a binary glTF file, along with the materials bound to them
+    pub(crate) fn export_glb(&self, path: &str, nodes: &[(MeshDescriptor, Mat4)]) -> Result<(), Box<dyn std::error::Error>> {
+        let mut scene = GltfScene::new();
+        let mut exported: HashMap<&MeshDescriptor, usize> = HashMap::new();
+        for (md, transform) in nodes {
+            let mesh = match self.meshes.get(&md.idx) {
+                Some(mesh) => mesh,
+                None => return Err(Box::new(RendError::new("Unknown mesh descriptor"))),
+            };
+            let idx = match exported.get(md) {
+                Some(idx) => *idx,
+                None => {
+                    let parts = md
+                        .parts(mesh.faces.len())
+                        .iter()
+                        .map(|part| {
+                            let material = self
+                                .material_sources
+                                .get(&part.material)
+                                .map(|source| scene.add_material(&format!("material{}", part.material), source));
+                            (part.start, part.end, material)
+                        })
+                        .collect();
+                    let idx = scene.add_mesh_parts(mesh, parts);
+                    exported.insert(md, idx);
+                    idx
+                }
+            };
+            scene.add_node(idx, *transform);
+        }
+        let mut file = std::io::BufWriter::new(std::fs::File::create(path)?);
+        scene.write_glb(&mut file)?;
+        Ok(())
+    }
+
+    fn mesh_descriptor(&self, idx: MeshSlot, path: &str) -> MeshDescriptor {
+        let mut md = MeshDescriptor::new(idx, path, self.default_material);
+        if let Some(parts) = self.mesh_parts.get(&idx) {
+            md.parts = parts.clone();
+        }
+        md
     }
 
     // create a pipeline with a given fragment shader
@@ -320,22 +584,64 @@ impl Graphics {
         )
     }
 
+    // create a point cloud pipeline with a given fragment shader
+    fn create_point_render_pipeline_for_shader(
+        &self,
+        device: &wgpu::Device,
+        shader: &wgpu::ShaderModule,
+    ) -> wgpu::RenderPipeline {
+        create_point_render_pipeline(
+            device,
+            &self.pipeline_layout,
+            &self.vs_mod,
+            shader,
+            wgpu::RenderPipelineBuilder::DEFAULT_COLOR_FORMAT,
+            wgpu::RenderPipelineBuilder::DEFAULT_DEPTH_FORMAT,
+            self.msaa,
+        )
+    }
+
+    // create a skinned pipeline with a given fragment shader
+    fn create_skinned_render_pipeline_for_shader(
+        &self,
+        device: &wgpu::Device,
+        shader: &wgpu::ShaderModule,
+    ) -> wgpu::RenderPipeline {
+        create_skinned_render_pipeline(
+            device,
+            &self.skinning.pipeline_layout,
+            &self.skinning.vs_mod,
+            shader,
+            wgpu::RenderPipelineBuilder::DEFAULT_COLOR_FORMAT,
+            wgpu::RenderPipelineBuilder::DEFAULT_DEPTH_FORMAT,
+            self.msaa,
+        )
+    }
+
     // push buffers from a draw call into a stack
     pub(crate) fn draw(
         &self,
         device: &wgpu::Device,
         buffers: &mut Vec<wgpu::Buffer>,
-        counts: &mut Vec<usize>,
         mesh: &Mesh,
     ) -> bool {
-        let indices_bytes = indices_as_bytes_copy(&mesh.faces);
+        let indices_bytes = indices_as_bytes_copy(&mesh.faces, mesh.index_format());
         let vertices_bytes = vertices_as_bytes_copy(&mesh.vertices);
         let uvs_bytes = vertices_as_bytes_copy(&mesh.uvs);
         let normals_bytes = vertices_as_bytes_copy(&mesh.normals);
+        let colors_bytes: Vec<u8> = mesh
+            .color_buffer()
+            .iter()
+            .flat_map(|color| color.to_array())
+            .flat_map(f32::to_le_bytes)
+            .collect();
+        let tangents_bytes: Vec<u8> = mesh
+            .tangent_buffer()
+            .iter()
+            .flat_map(|tangent| tangent.to_array())
+            .flat_map(f32::to_le_bytes)
+            .collect();
 
-        let index_count = mesh.faces.len();
-
-        counts.push(index_count);
         buffers.push(device.create_buffer_init(&wgpu::BufferInitDescriptor {
             label: None,
             contents: &*indices_bytes,
@@ -356,7 +662,58 @@ impl Graphics {
             contents: &*normals_bytes,
             usage: wgpu::BufferUsages::VERTEX,
         }));
+        buffers.push(device.create_buffer_init(&wgpu::BufferInitDescriptor {
+            label: None,
+            contents: &colors_bytes,
+            usage: wgpu::BufferUsages::VERTEX,
+        }));
+        buffers.push(device.create_buffer_init(&wgpu::BufferInitDescriptor {
+            label: None,
+            contents: &tangents_bytes,
+            usage: wgpu::BufferUsages::VERTEX,
+        }));
 
         return true;
     }
+
+    // push the joints and weights of a skinned draw call into a stack, interleaved in one buffer
+    pub(crate) fn draw_skin(
+        &self,
+        device: &wgpu::Device,
+        buffers: &mut Vec<wgpu::Buffer>,
+        mesh: &Mesh,
+    ) {
+        let (joints, weights) = mesh.skin_buffers();
+        let skin_bytes: Vec<u8> = joints
+            .iter()
+            .zip(&weights)
+            .flat_map(|(joints, weights)| {
+                let joints = joints.iter().flat_map(|j| j.to_le_bytes());
+                joints.chain(weights.iter().flat_map(|w| w.to_le_bytes())).collect::<Vec<u8>>()
+            })
+            .collect();
+
+        buffers.push(device.create_buffer_init(&wgpu::BufferInitDescriptor {
+            label: None,
+            contents: &skin_bytes,
+            usage: wgpu::BufferUsages::VERTEX,
+        }));
+    }
+
+    /// upload bone matrices and bind them for a single skinned draw call
+    ///
+    /// an empty pose is drawn as the identity
+    pub(crate) fn bone_bind_group(&self, device: &wgpu::Device, pose: &[Mat4]) -> (wgpu::Buffer, wgpu::BindGroup) {
+        let pose = if pose.is_empty() { vec![Mat4::IDENTITY] } else { pose.to_vec() };
+        let bones_bytes = matrices_as_bytes_copy(&pose);
+        let buffer = device.create_buffer_init(&wgpu::BufferInitDescriptor {
+            label: None,
+            contents: &bones_bytes,
+            usage: wgpu::BufferUsages::STORAGE,
+        });
+        let group = wgpu::BindGroupBuilder::new()
+            .buffer_bytes(&buffer, 0, None)
+            .build(device, &self.skinning.bone_layout);
+        (buffer, group)
+    }
 }
@@ -1,7 +1,9 @@
+pub mod animation;
 pub mod app;
 pub mod camera;
 pub mod camera_controller;
 pub mod error;
+pub mod frustum;
 pub mod graphics;
 pub mod mesh;
 pub mod process;
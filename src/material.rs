@@ -1,9 +1,27 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
 use crate::texture::Texture;
 use crate::graphics::{Graphics, ShaderSlot};
 use crate::wgpu;
 use crate::glam::Vec4;
 use nannou::prelude::DeviceExt;
 
+/// Map slot of the diffuse texture
+pub const DIFFUSE_MAP: usize = 0;
+/// Map slot of the normal texture
+pub const NORMAL_MAP: usize = 1;
+/// Map slot of the specular texture
+pub const SPECULAR_MAP: usize = 2;
+
+/// texture used when a map slot has not been set
+pub(crate) fn default_map(slot: usize) -> &'static str {
+    match slot {
+        NORMAL_MAP => "dev/nm.png",
+        _ => "dev/white.png",
+    }
+}
+
 #[repr(C)]
 #[derive(Copy, Clone, Debug)]
 pub struct MaterialData {
@@ -40,6 +58,8 @@ impl MaterialData {
 pub struct MaterialDescriptor {
     pub data: MaterialData,
     pub maps: Vec<String>,
+    /// encoded images of the maps embedded in their source file, by slot
+    pub(crate) map_bytes: HashMap<usize, Arc<[u8]>>,
     pub shader: Option<String>,
 }
 
@@ -48,9 +68,25 @@ impl MaterialDescriptor {
         Self {
             data: MaterialData::new(),
             maps: vec![],
+            map_bytes: HashMap::new(),
             shader: None,
         }
     }
+
+    /// set the texture of a map slot, slots before it are filled with their default textures
+    pub fn set_map(&mut self, slot: usize, path: &str) {
+        while self.maps.len() <= slot {
+            self.maps.push(default_map(self.maps.len()).into());
+        }
+        self.maps[slot] = path.into();
+        self.map_bytes.remove(&slot);
+    }
+
+    /// set the texture of a map slot from an encoded image in memory, `name` identifies it like a path would
+    pub fn set_map_bytes(&mut self, slot: usize, name: &str, bytes: &[u8]) {
+        self.set_map(slot, name);
+        self.map_bytes.insert(slot, Arc::from(bytes));
+    }
 }
 
 pub(crate) struct Material {
@@ -90,13 +126,13 @@ impl Material {
             if let Some(p) = mat.maps.get(i) {
                 path = p.as_str();
             } else {
-                path = match i {
-                    0 => "dev/white.png",
-                    1 => "dev/nm.png",
-                    _ => "dev/white.png",
-                }
+                path = default_map(i);
             }
-            if let Ok(tex) = Texture::from_file(device, queue, path, "dynamic") {
+            let texture = match mat.map_bytes.get(&i) {
+                Some(bytes) => Texture::from_bytes(device, queue, bytes, path),
+                None => Texture::from_file(device, queue, path, "dynamic"),
+            };
+            if let Ok(tex) = texture {
                 maps.push(tex);
             } else {
                 maps.push(Texture::new(device, Some("black")));
@@ -0,0 +1,150 @@
+//! Bounding volumes
+//!
+//! Boxes and spheres enclosing a mesh, computed once when it is loaded with [`super::Mesh::aabb()`]
+//! and [`super::Mesh::bounding_sphere()`], then moved along its instances with their `transform` methods
+
+use glam::Mat4;
+
+use crate::Vec3;
+
+/// An axis aligned bounding box
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Aabb {
+    pub min: Vec3,
+    pub max: Vec3,
+}
+
+impl Aabb {
+    pub fn new(min: Vec3, max: Vec3) -> Aabb {
+        Aabb { min, max }
+    }
+
+    /// The smallest box holding every point, an empty box at the origin when there are none
+    pub fn from_points(points: &[Vec3]) -> Aabb {
+        let Some(&first) = points.first() else {
+            return Aabb::default();
+        };
+        points.iter().fold(Aabb::new(first, first), |aabb, &point| {
+            Aabb::new(aabb.min.min(point), aabb.max.max(point))
+        })
+    }
+
+    pub fn center(&self) -> Vec3 {
+        (self.min + self.max) * 0.5
+    }
+
+    pub fn size(&self) -> Vec3 {
+        self.max - self.min
+    }
+
+    pub fn contains(&self, point: Vec3) -> bool {
+        point.cmpge(self.min).all() && point.cmple(self.max).all()
+    }
+
+    pub fn intersects(&self, other: &Aabb) -> bool {
+        self.min.cmple(other.max).all() && other.min.cmple(self.max).all()
+    }
+
+    /// The smallest box holding both boxes
+    pub fn union(&self, other: &Aabb) -> Aabb {
+        Aabb::new(self.min.min(other.min), self.max.max(other.max))
+    }
+
+    /// The box holding this one once transformed, such as by an instance matrix
+    ///
+    /// rotated boxes grow to stay aligned with the axes
+    pub fn transform(&self, matrix: Mat4) -> Aabb {
+        let center = matrix.transform_point3(self.center());
+        let half = self.size() * 0.5;
+        let extents = matrix.x_axis.truncate().abs() * half.x
+            + matrix.y_axis.truncate().abs() * half.y
+            + matrix.z_axis.truncate().abs() * half.z;
+        Aabb::new(center - extents, center + extents)
+    }
+}
+
+/// A sphere enclosing a mesh, cheaper than an [`Aabb`] to transform and test
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct BoundingSphere {
+    pub center: Vec3,
+    pub radius: f32,
+}
+
+impl BoundingSphere {
+    pub fn new(center: Vec3, radius: f32) -> BoundingSphere {
+        BoundingSphere { center, radius }
+    }
+
+    /// A sphere around the center of the points' box, reaching the farthest of them
+    pub fn from_points(points: &[Vec3]) -> BoundingSphere {
+        let center = Aabb::from_points(points).center();
+        let radius = points.iter().map(|point| point.distance(center)).fold(0., f32::max);
+        BoundingSphere::new(center, radius)
+    }
+
+    pub fn contains(&self, point: Vec3) -> bool {
+        point.distance_squared(self.center) <= self.radius * self.radius
+    }
+
+    pub fn intersects(&self, other: &BoundingSphere) -> bool {
+        let reach = self.radius + other.radius;
+        self.center.distance_squared(other.center) <= reach * reach
+    }
+
+    /// The sphere holding this one once transformed, such as by an instance matrix
+    ///
+    /// non uniform scales grow the radius by their largest axis
+    pub fn transform(&self, matrix: Mat4) -> BoundingSphere {
+        let scale = [matrix.x_axis, matrix.y_axis, matrix.z_axis]
+            .iter()
+            .map(|axis| axis.truncate().length())
+            .fold(0., f32::max);
+        BoundingSphere::new(matrix.transform_point3(self.center), self.radius * scale)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use glam::Quat;
+
+    #[test]
+    fn boxes_follow_their_transform() {
+        let aabb = Aabb::new(Vec3::new(-1., -2., -3.), Vec3::new(1., 2., 3.));
+        let moved = aabb.transform(Mat4::from_scale_rotation_translation(Vec3::splat(2.), Quat::IDENTITY, Vec3::X));
+        assert_eq!(moved, Aabb::new(Vec3::new(-1., -4., -6.), Vec3::new(3., 4., 6.)));
+
+        // a quarter turn around Z swaps the extents along X and Y
+        let turned = aabb.transform(Mat4::from_rotation_z(std::f32::consts::FRAC_PI_2));
+        assert!(turned.min.abs_diff_eq(Vec3::new(-2., -1., -3.), 1e-6));
+        assert!(turned.max.abs_diff_eq(Vec3::new(2., 1., 3.), 1e-6));
+
+        // rotated boxes grow to hold every corner
+        let unit = Aabb::new(Vec3::splat(-1.), Vec3::ONE);
+        let tilted = unit.transform(Mat4::from_rotation_z(std::f32::consts::FRAC_PI_4));
+        assert!(tilted.max.abs_diff_eq(Vec3::new(2f32.sqrt(), 2f32.sqrt(), 1.), 1e-6));
+    }
+
+    #[test]
+    fn spheres_follow_their_transform() {
+        let sphere = BoundingSphere::new(Vec3::X, 1.);
+        let moved = sphere.transform(Mat4::from_translation(Vec3::Y) * Mat4::from_rotation_z(std::f32::consts::FRAC_PI_2));
+        assert!(moved.center.abs_diff_eq(Vec3::new(0., 2., 0.), 1e-6));
+        assert_eq!(moved.radius, 1.);
+
+        // non uniform scales grow the radius by their largest axis
+        let stretched = sphere.transform(Mat4::from_scale(Vec3::new(1., 3., 2.)));
+        assert_eq!(stretched, BoundingSphere::new(Vec3::X, 3.));
+    }
+
+    #[test]
+    fn unions_hold_both_boxes() {
+        let a = Aabb::new(Vec3::ZERO, Vec3::ONE);
+        let b = Aabb::new(Vec3::new(-1., 0.5, 0.5), Vec3::new(0.5, 2., 0.75));
+        let union = a.union(&b);
+        assert_eq!(union, Aabb::new(Vec3::new(-1., 0., 0.), Vec3::new(1., 2., 1.)));
+        assert_eq!(union, b.union(&a));
+        assert_eq!(a.union(&a), a);
+        assert!([a.min, a.max, b.min, b.max].iter().all(|&corner| union.contains(corner)));
+    }
+}
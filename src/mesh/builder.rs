@@ -0,0 +1,170 @@
+//! Procedural meshes
+//!
+//! Build a [`Mesh`] from buffers generated at runtime, directly with [`Mesh::from_buffers()`]
+//! or face by face with a [`MeshBuilder`]
+
+use crate::error::RendError;
+use crate::Vec3;
+
+use super::solver::smooth_normals;
+use super::{Indices, Mesh, Normals, Vertices};
+
+impl Mesh {
+    /// Create a mesh from indexed buffers, each three indices forming a triangle
+    ///
+    /// `uvs` and `normals` hold a value per position, or are empty:
+    /// missing uvs are left to zero and missing normals are smoothed from the faces.
+    /// This function will return an error when the buffers don't match
+    pub fn from_buffers(indices: Indices, positions: Vertices, uvs: Vertices, normals: Normals) -> Result<Mesh, RendError> {
+        if !indices.len().is_multiple_of(3) {
+            return Err(RendError::new("Mesh indices don't form whole triangles"));
+        }
+        if let Some(index) = indices.iter().find(|&&index| index as usize >= positions.len()) {
+            return Err(RendError::new(&format!("Mesh index {} is out of its {} positions", index, positions.len())));
+        }
+        for (name, len) in [("uvs", uvs.len()), ("normals", normals.len())] {
+            if len != 0 && len != positions.len() {
+                return Err(RendError::new(&format!("Mesh has {} {} for {} positions", len, name, positions.len())));
+            }
+        }
+
+        let mut mesh = Mesh::new();
+        mesh.uvs = if uvs.is_empty() { vec![Vec3::ZERO; positions.len()] } else { uvs };
+        mesh.normals = if normals.is_empty() { smooth_normals(&positions, &indices) } else { normals };
+        mesh.faces = indices;
+        mesh.vertices = positions;
+        mesh.update_bounds();
+        Ok(mesh)
+    }
+}
+
+/// Build a mesh vertex by vertex and face by face
+///
+/// ```
+/// # use rend_ox::mesh::MeshBuilder;
+/// # use rend_ox::Vec3;
+/// # fn main() -> Result<(), rend_ox::error::RendError> {
+/// let mut builder = MeshBuilder::new();
+/// let a = builder.add_vertex(Vec3::new(0., 0., 0.), Vec3::new(0., 0., 0.), Vec3::Z);
+/// let b = builder.add_vertex(Vec3::new(1., 0., 0.), Vec3::new(1., 0., 0.), Vec3::Z);
+/// let c = builder.add_vertex(Vec3::new(1., 1., 0.), Vec3::new(1., 1., 0.), Vec3::Z);
+/// let d = builder.add_vertex(Vec3::new(0., 1., 0.), Vec3::new(0., 1., 0.), Vec3::Z);
+/// builder.add_quad(a, b, c, d);
+/// let quad = builder.build()?;
+/// assert_eq!(quad.buffers().0.len(), 6);
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct MeshBuilder {
+    indices: Indices,
+    positions: Vertices,
+    uvs: Vertices,
+    normals: Normals,
+    groups: Vec<(u32, String)>,
+}
+
+impl MeshBuilder {
+    pub fn new() -> MeshBuilder {
+        MeshBuilder::default()
+    }
+
+    /// Add a vertex and give its index, a zero normal is smoothed from the faces using it
+    pub fn add_vertex(&mut self, position: Vec3, uv: Vec3, normal: Vec3) -> u32 {
+        self.positions.push(position);
+        self.uvs.push(uv);
+        self.normals.push(normal);
+        self.positions.len() as u32 - 1
+    }
+
+    /// Add a triangle from the indices of its vertices, counter-clockwise when seen from the front
+    pub fn add_triangle(&mut self, a: u32, b: u32, c: u32) {
+        self.indices.extend([a, b, c]);
+    }
+
+    /// Add a quad split into two triangles along its `a`-`c` diagonal, see [`Self::add_triangle()`]
+    pub fn add_quad(&mut self, a: u32, b: u32, c: u32, d: u32) {
+        self.add_triangle(a, b, c);
+        self.add_triangle(a, c, d);
+    }
+
+    /// Start a named group, following faces belong to it until the next one
+    pub fn group(&mut self, name: &str) {
+        let start = self.indices.len() as u32;
+        if self.groups.last().is_some_and(|(last, _)| *last == start) {
+            self.groups.pop();
+        }
+        self.groups.push((start, name.into()));
+    }
+
+    /// Create the mesh, failing when a face refers to a vertex that wasn't added
+    pub fn build(self) -> Result<Mesh, RendError> {
+        let mut mesh = Mesh::from_buffers(self.indices, self.positions, self.uvs, self.normals)?;
+        if mesh.normals.contains(&Vec3::ZERO) {
+            let smooth = smooth_normals(&mesh.vertices, &mesh.faces);
+            for (normal, smooth) in mesh.normals.iter_mut().zip(smooth) {
+                if *normal == Vec3::ZERO {
+                    *normal = smooth;
+                }
+            }
+        }
+        mesh.groups = self.groups;
+        Ok(mesh)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TRIANGLE: [Vec3; 3] = [Vec3::ZERO, Vec3::X, Vec3::Y];
+
+    fn build_error(indices: Indices, uvs: Vertices, normals: Normals) -> String {
+        match Mesh::from_buffers(indices, TRIANGLE.to_vec(), uvs, normals) {
+            Ok(_) => panic!("mismatched buffers built"),
+            Err(why) => why.to_string(),
+        }
+    }
+
+    #[test]
+    fn mismatched_buffers_are_rejected() {
+        assert_eq!(build_error(vec![0, 1], vec![], vec![]), "Mesh indices don't form whole triangles");
+        assert_eq!(build_error(vec![0, 1, 3], vec![], vec![]), "Mesh index 3 is out of its 3 positions");
+        assert_eq!(build_error(vec![0, 1, 2], vec![Vec3::ZERO; 2], vec![]), "Mesh has 2 uvs for 3 positions");
+        assert_eq!(build_error(vec![0, 1, 2], vec![], vec![Vec3::Z; 4]), "Mesh has 4 normals for 3 positions");
+    }
+
+    #[test]
+    fn missing_attributes_are_filled() {
+        let mesh = Mesh::from_buffers(vec![0, 1, 2], TRIANGLE.to_vec(), vec![], vec![]).unwrap();
+        assert_eq!(mesh.uvs, [Vec3::ZERO; 3]);
+        assert_eq!(mesh.normals, [Vec3::Z; 3]);
+        assert_eq!(mesh.aabb().max, Vec3::new(1., 1., 0.));
+    }
+
+    #[test]
+    fn builders_smooth_zero_normals_only() {
+        let mut builder = MeshBuilder::new();
+        let corners = TRIANGLE.map(|position| builder.add_vertex(position, position, Vec3::ZERO));
+        let top = builder.add_vertex(Vec3::new(1., 1., 0.), Vec3::ONE, -Vec3::Z);
+        builder.group("bottom");
+        builder.add_triangle(corners[0], corners[1], corners[2]);
+        builder.group("skipped");
+        builder.group("top");
+        builder.add_triangle(corners[1], top, corners[2]);
+
+        let mesh = builder.build().unwrap();
+        assert_eq!(mesh.faces, [0, 1, 2, 1, 3, 2]);
+        assert_eq!(mesh.normals, [Vec3::Z, Vec3::Z, Vec3::Z, -Vec3::Z]);
+        assert_eq!(mesh.uvs[3], Vec3::ONE);
+        assert_eq!(mesh.groups, [(0, "bottom".into()), (3, "top".into())]);
+    }
+
+    #[test]
+    fn builders_reject_unknown_vertices() {
+        let mut builder = MeshBuilder::new();
+        builder.add_vertex(Vec3::ZERO, Vec3::ZERO, Vec3::Z);
+        builder.add_triangle(0, 1, 2);
+        assert!(builder.build().is_err());
+    }
+}
@@ -6,19 +6,66 @@
 
 use crate::graphics::{MaterialSlot, MeshSlot};
 
+/// A range of a mesh's indices drawn with a single material
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub(crate) struct MeshPart {
+    pub(crate) start: u32,
+    pub(crate) end: u32,
+    pub(crate) material: MaterialSlot,
+}
+
 #[derive(Clone, Debug, PartialEq, Eq, Hash)]
 pub struct MeshDescriptor {
     pub name: String,
+    /// object or group of the mesh this descriptor is restricted to, the whole mesh when `None`
+    pub group: Option<String>,
     pub(crate) idx: MeshSlot,
     pub(crate) material: MaterialSlot,
+    /// materials bound to ranges of the mesh, the whole mesh uses `material` when empty
+    pub(crate) parts: Vec<MeshPart>,
 }
 
 impl MeshDescriptor {
     pub fn new(idx: MeshSlot, path: &str, material: MaterialSlot) -> MeshDescriptor {
         MeshDescriptor {
             name: path.into(),
+            group: None,
             idx,
             material,
+            parts: vec![],
+        }
+    }
+
+    /// The parts to draw for a mesh of `index_count` indices
+    pub(crate) fn parts(&self, index_count: usize) -> Vec<MeshPart> {
+        if self.parts.is_empty() {
+            return vec![MeshPart {
+                start: 0,
+                end: index_count as u32,
+                material: self.material,
+            }];
+        }
+        self.parts.clone()
+    }
+
+    /// A descriptor drawing only the `start..end` index range of this one's mesh
+    pub(crate) fn submesh(&self, group: &str, start: u32, end: u32, index_count: usize) -> MeshDescriptor {
+        let parts = self
+            .parts(index_count)
+            .into_iter()
+            .filter(|part| part.start < end && part.end > start)
+            .map(|part| MeshPart {
+                start: part.start.max(start),
+                end: part.end.min(end),
+                material: part.material,
+            })
+            .collect();
+        MeshDescriptor {
+            name: self.name.clone(),
+            group: Some(group.into()),
+            idx: self.idx,
+            material: self.material,
+            parts,
         }
     }
 }
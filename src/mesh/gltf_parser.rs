@@ -0,0 +1,594 @@
+//! glTF 2.0 importer
+//!
+//! Loads .gltf files, with external or embedded buffers, and binary .glb files.
+//! Every mesh of the scene is flattened into a single [`Mesh`], moved by the transform of its node,
+//! each node holding a mesh becoming a group. The joints of every skin become bones,
+//! and the animations moving them can be loaded as [`AnimationClip`]s
+
+use std::collections::HashMap;
+use std::path::Path;
+
+use glam::{Mat3, Mat4, Quat, Vec4};
+use gltf::animation::util::ReadOutputs;
+use gltf::animation::{Interpolation, Property};
+use gltf::image::Source;
+use gltf::mesh::Mode;
+use gltf::{Document, Node};
+
+use crate::animation::{AnimationClip, BoneKeyframe};
+use crate::error::{MeshParseError, MeshParseErrorKind};
+use crate::material::{MaterialDescriptor, DIFFUSE_MAP, NORMAL_MAP, SPECULAR_MAP};
+use crate::Vec3;
+
+use super::mtl_parser::MaterialLibrary;
+use super::solver::smooth_normals;
+use super::{Bone, Mesh};
+
+type Buffers = Vec<gltf::buffer::Data>;
+
+fn parse_error(file_name: &str, kind: MeshParseErrorKind) -> MeshParseError {
+    MeshParseError::new(file_name, 0, 0, kind)
+}
+
+fn gltf_error(file_name: &str, why: gltf::Error) -> MeshParseError {
+    match why {
+        gltf::Error::Io(why) => parse_error(file_name, MeshParseErrorKind::Io(why)),
+        why => parse_error(file_name, MeshParseErrorKind::Gltf(why)),
+    }
+}
+
+fn load_document(file_name: &str) -> Result<(Document, Buffers), MeshParseError> {
+    let gltf::Gltf { document, blob } = gltf::Gltf::open(file_name).map_err(|why| gltf_error(file_name, why))?;
+    let base = Path::new(file_name).parent();
+    let buffers = gltf::import_buffers(&document, base, blob).map_err(|why| gltf_error(file_name, why))?;
+    Ok((document, buffers))
+}
+
+fn node_name(node: &Node) -> String {
+    match node.name() {
+        Some(name) => name.into(),
+        None => format!("node{}", node.index()),
+    }
+}
+
+fn local_transform(node: &Node) -> Mat4 {
+    Mat4::from_cols_array_2d(&node.transform().matrix())
+}
+
+/// parent of each node, `None` for roots
+fn node_parents(document: &Document) -> Vec<Option<usize>> {
+    let mut parents = vec![None; document.nodes().len()];
+    for node in document.nodes() {
+        for child in node.children() {
+            parents[child.index()] = Some(node.index());
+        }
+    }
+    parents
+}
+
+/// nodes of the scene in depth first order, with their world transform
+fn scene_nodes<'a>(document: &'a Document, parents: &[Option<usize>]) -> Vec<(Node<'a>, Mat4)> {
+    let roots: Vec<Node> = match document.default_scene().or_else(|| document.scenes().next()) {
+        Some(scene) => scene.nodes().collect(),
+        None => document.nodes().filter(|node| parents[node.index()].is_none()).collect(),
+    };
+    let mut stack: Vec<(Node, Mat4)> = roots.into_iter().rev().map(|node| (node, Mat4::IDENTITY)).collect();
+    let mut nodes = vec![];
+    while let Some((node, parent)) = stack.pop() {
+        let world = parent * local_transform(&node);
+        for child in node.children().collect::<Vec<_>>().into_iter().rev() {
+            stack.push((child, world));
+        }
+        nodes.push((node, world));
+    }
+    nodes
+}
+
+/// product of the local transforms of the ancestors of a node, up to the closest one accepted by `is_bone`
+fn ancestors_transform(document: &Document, parents: &[Option<usize>], node: usize, is_bone: impl Fn(usize) -> bool) -> Mat4 {
+    let mut transform = Mat4::IDENTITY;
+    let mut ancestor = parents[node];
+    while let Some(idx) = ancestor {
+        if is_bone(idx) {
+            break;
+        }
+        if let Some(node) = document.nodes().nth(idx) {
+            transform = local_transform(&node) * transform;
+        }
+        ancestor = parents[idx];
+    }
+    transform
+}
+
+fn material_name(material: &gltf::Material) -> Option<String> {
+    let idx = material.index()?;
+    Some(match material.name() {
+        Some(name) => name.into(),
+        None => format!("material{}", idx),
+    })
+}
+
+/// glTF uvs start from the top of the image, unlike the other formats
+fn flip_uv(uv: [f32; 2]) -> Vec3 {
+    Vec3::new(uv[0], 1. - uv[1], 0.)
+}
+
+/// Map a PBR metallic roughness material to a descriptor
+///
+/// the base color gives the diffuse color, metals reflect their own color and rough materials are less shiny.
+/// Textures are set as the diffuse, normal and specular maps,
+/// those embedded as data uris aren't supported and are left out with a warning
+fn material_descriptor(
+    material: &gltf::Material,
+    buffers: &Buffers,
+    file_name: &str,
+    warnings: &mut Vec<MeshParseError>,
+) -> Result<MaterialDescriptor, MeshParseError> {
+    let pbr = material.pbr_metallic_roughness();
+    let color = Vec4::from(pbr.base_color_factor());
+    let specular = Vec3::splat(0.04).lerp(color.truncate(), pbr.metallic_factor());
+    let mut descriptor = MaterialDescriptor::new();
+    descriptor.data.color = color;
+    descriptor.data.specular = specular.extend(1. - pbr.roughness_factor());
+
+    let textures = [
+        (DIFFUSE_MAP, pbr.base_color_texture().map(|info| info.texture())),
+        (NORMAL_MAP, material.normal_texture().map(|info| info.texture())),
+        (SPECULAR_MAP, pbr.metallic_roughness_texture().map(|info| info.texture())),
+    ];
+    for (slot, texture) in textures {
+        let image = match texture {
+            Some(texture) => texture.source(),
+            None => continue,
+        };
+        match image.source() {
+            Source::View { view, .. } => {
+                let bytes = buffers
+                    .get(view.buffer().index())
+                    .and_then(|buffer| buffer.get(view.offset()..)?.get(..view.length()))
+                    .ok_or_else(|| parse_error(file_name, MeshParseErrorKind::IndexOutOfRange))?;
+                let name = format!("{}#image{}", file_name, image.index());
+                descriptor.set_map_bytes(slot, &name, bytes);
+            }
+            Source::Uri { uri, .. } if uri.starts_with("data:") => {
+                let feature = format!("data uri of image {}", image.index());
+                warnings.push(parse_error(file_name, MeshParseErrorKind::UnsupportedFeature(feature)));
+            }
+            Source::Uri { uri, .. } => {
+                let base = Path::new(file_name).parent().unwrap_or(Path::new(""));
+                descriptor.set_map(slot, &base.join(uri).to_string_lossy());
+            }
+        }
+    }
+    Ok(descriptor)
+}
+
+/// The joints of every skin as bones, and the bone index of each joint node
+///
+/// the reference pose of a bone is its bind pose, given by the inverse bind matrices of its skin
+fn skin_bones(document: &Document, buffers: &Buffers, parents: &[Option<usize>]) -> (Vec<Bone>, HashMap<usize, u32>) {
+    let mut joints: Vec<(Node, Mat4)> = vec![];
+    let mut bone_of: HashMap<usize, u32> = HashMap::new();
+    for skin in document.skins() {
+        let reader = skin.reader(|buffer| Some(&buffers[buffer.index()]));
+        let inverse_binds: Vec<Mat4> = match reader.read_inverse_bind_matrices() {
+            Some(matrices) => matrices.map(|matrix| Mat4::from_cols_array_2d(&matrix)).collect(),
+            None => vec![],
+        };
+        for (i, joint) in skin.joints().enumerate() {
+            if bone_of.contains_key(&joint.index()) {
+                continue;
+            }
+            let bind = inverse_binds.get(i).copied().unwrap_or(Mat4::IDENTITY).inverse();
+            bone_of.insert(joint.index(), joints.len() as u32);
+            joints.push((joint, bind));
+        }
+    }
+
+    let bones = joints
+        .iter()
+        .enumerate()
+        .map(|(idx, (joint, bind))| {
+            let mut parent = parents[joint.index()];
+            while let Some(node) = parent.filter(|node| !bone_of.contains_key(node)) {
+                parent = parents[node];
+            }
+            let parent = parent.map(|node| bone_of[&node]);
+            let parent_bind = match parent {
+                Some(parent) => joints[parent as usize].1,
+                None => Mat4::IDENTITY,
+            };
+            Bone {
+                idx: idx as u32,
+                name: node_name(joint),
+                pose: parent_bind.inverse() * *bind,
+                parent: parent.map_or(-1, |parent| parent as i32),
+            }
+        })
+        .collect();
+    (bones, bone_of)
+}
+
+impl Mesh {
+    /// Load every mesh of a glTF 2.0 scene from a .gltf or .glb file
+    ///
+    /// Meshes are moved to their place in the scene and each node holding one becomes a group,
+    /// skinned meshes stay in their bind pose and keep their joints as bones.
+    /// Materials are read from their PBR metallic roughness parameters, embedded textures included.
+    /// Vertex colors are kept, vertices of primitives without any being white.
+    /// Primitives other than triangles are skipped, and listed in [`Self::warnings()`]
+    pub fn from_gltf(file_name: &str) -> Result<Mesh, MeshParseError> {
+        let (document, buffers) = load_document(file_name)?;
+        let parents = node_parents(&document);
+        let (bones, bone_of) = skin_bones(&document, &buffers, &parents);
+
+        let mut mesh = Mesh::new();
+        mesh.path = file_name.into();
+        mesh.bones = bones;
+        let base = Path::new(file_name).parent().unwrap_or(Path::new(""));
+        mesh.dependencies = document
+            .buffers()
+            .filter_map(|buffer| match buffer.source() {
+                gltf::buffer::Source::Uri(uri) if !uri.starts_with("data:") => {
+                    Some(base.join(uri).to_string_lossy().into_owned())
+                }
+                _ => None,
+            })
+            .collect();
+        let mut material_sources = MaterialLibrary::new();
+        let mut tangents: Vec<Option<Vec4>> = vec![];
+        let mut colors: Vec<Option<Vec4>> = vec![];
+
+        for (node, world) in scene_nodes(&document, &parents) {
+            let node_mesh = match node.mesh() {
+                Some(node_mesh) => node_mesh,
+                None => continue,
+            };
+            // skinned meshes are placed by their joints rather than by their node
+            let transform = match node.skin() {
+                Some(_) => Mat4::IDENTITY,
+                None => world,
+            };
+            let normal_matrix = Mat3::from_mat4(transform).inverse().transpose();
+            let skin_joints: Vec<u32> = match node.skin() {
+                Some(skin) => skin.joints().map(|joint| bone_of[&joint.index()]).collect(),
+                None => vec![],
+            };
+            mesh.groups.push((mesh.faces.len() as u32, node_name(&node)));
+
+            for primitive in node_mesh.primitives() {
+                if primitive.mode() != Mode::Triangles {
+                    let feature = format!("{:?} primitive of \"{}\"", primitive.mode(), node_name(&node));
+                    mesh.warnings.push(parse_error(file_name, MeshParseErrorKind::UnsupportedFeature(feature)));
+                    continue;
+                }
+                let reader = primitive.reader(|buffer| Some(&buffers[buffer.index()]));
+                let positions: Vec<Vec3> = match reader.read_positions() {
+                    Some(positions) => positions.map(Vec3::from).collect(),
+                    None => return Err(parse_error(file_name, MeshParseErrorKind::MissingValue)),
+                };
+                let mut indices: Vec<u32> = match reader.read_indices() {
+                    Some(indices) => indices.into_u32().collect(),
+                    None => (0..positions.len() as u32).collect(),
+                };
+                if indices.iter().any(|&index| index as usize >= positions.len()) {
+                    return Err(parse_error(file_name, MeshParseErrorKind::IndexOutOfRange));
+                }
+                indices.truncate(indices.len() - indices.len() % 3);
+                let normals: Vec<Vec3> = match reader.read_normals() {
+                    Some(normals) => normals.map(Vec3::from).collect(),
+                    None => smooth_normals(&positions, &indices),
+                };
+                // mirroring transforms turn faces inside out
+                if transform.determinant() < 0. {
+                    for face in indices.chunks_exact_mut(3) {
+                        face.swap(1, 2);
+                    }
+                }
+
+                let offset = mesh.vertices.len() as u32;
+                mesh.vertices.extend(positions.iter().map(|&position| transform.transform_point3(position)));
+                mesh.normals.extend(normals.iter().map(|&normal| (normal_matrix * normal).normalize_or_zero()));
+                match reader.read_tex_coords(0) {
+                    Some(uvs) => mesh.uvs.extend(uvs.into_f32().map(flip_uv)),
+                    None => mesh.uvs.extend(positions.iter().map(|_| Vec3::ZERO)),
+                }
+                match reader.read_tangents() {
+                    Some(read) => tangents.extend(read.map(|tangent| {
+                        let direction = transform.transform_vector3(Vec4::from(tangent).truncate());
+                        Some(direction.normalize_or_zero().extend(tangent[3]))
+                    })),
+                    None => tangents.extend(positions.iter().map(|_| None)),
+                }
+                match reader.read_colors(0) {
+                    Some(read) => colors.extend(read.into_rgba_f32().map(|color| Some(Vec4::from(color)))),
+                    None => colors.extend(positions.iter().map(|_| None)),
+                }
+                match (reader.read_joints(0), reader.read_weights(0)) {
+                    (Some(joints), Some(weights)) => {
+                        mesh.weights.extend(joints.into_u16().zip(weights.into_f32()).map(|(joints, weights)| {
+                            joints
+                                .iter()
+                                .zip(weights)
+                                .filter(|(_, weight)| *weight > 0.)
+                                .filter_map(|(&joint, weight)| Some((*skin_joints.get(joint as usize)?, weight)))
+                                .collect()
+                        }))
+                    }
+                    _ => mesh.weights.extend(positions.iter().map(|_| vec![])),
+                }
+
+                let name = material_name(&primitive.material());
+                if let Some(name) = &name {
+                    if !material_sources.contains_key(name) {
+                        let descriptor =
+                            material_descriptor(&primitive.material(), &buffers, file_name, &mut mesh.warnings)?;
+                        material_sources.insert(name.clone(), descriptor);
+                    }
+                }
+                if mesh.materials.last().is_none_or(|(_, last)| *last != name) {
+                    mesh.materials.push((mesh.faces.len() as u32, name));
+                }
+                mesh.faces.extend(indices.iter().map(|index| index + offset));
+            }
+        }
+
+        if tangents.iter().any(|tangent| tangent.is_some()) {
+            mesh.tangents = tangents.iter().map(|tangent| tangent.unwrap_or(Vec4::new(1., 0., 0., 1.))).collect();
+        }
+        if colors.iter().any(|color| color.is_some()) {
+            mesh.colors = colors.iter().map(|color| color.unwrap_or(Vec4::ONE)).collect();
+        }
+        if mesh.weights.iter().all(|weights| weights.is_empty()) {
+            mesh.weights = vec![];
+        }
+        mesh.material_sources = material_sources;
+        mesh.update_bounds();
+        Ok(mesh)
+    }
+}
+
+/// keys of a single property of a node, sorted by time
+struct Channel<T> {
+    keys: Vec<(f32, T)>,
+    step: bool,
+}
+
+impl<T: Copy> Channel<T> {
+    fn sample(&self, time: f32, interpolate: impl Fn(T, T, f32) -> T) -> Option<T> {
+        let next = self.keys.partition_point(|(key_time, _)| *key_time <= time);
+        match (next.checked_sub(1).map(|i| self.keys[i]), self.keys.get(next).copied()) {
+            (Some((_, a)), Some(_)) if self.step => Some(a),
+            (Some((ta, a)), Some((tb, b))) => Some(interpolate(a, b, (time - ta) / (tb - ta))),
+            (Some((_, key)), None) | (None, Some((_, key))) => Some(key),
+            (None, None) => None,
+        }
+    }
+}
+
+fn read_channel<T>(channel: &gltf::animation::Channel, inputs: Vec<f32>, outputs: Vec<T>) -> Channel<T> {
+    let interpolation = channel.sampler().interpolation();
+    // cubic splines store an in tangent, the value and an out tangent for each key
+    let values: Vec<T> = match interpolation {
+        Interpolation::CubicSpline => outputs.into_iter().skip(1).step_by(3).collect(),
+        _ => outputs,
+    };
+    Channel {
+        keys: inputs.into_iter().zip(values).collect(),
+        step: interpolation == Interpolation::Step,
+    }
+}
+
+impl AnimationClip {
+    /// Load every animation of a .gltf or .glb file, against the skeleton of `mesh`
+    ///
+    /// Bones are matched with nodes by name, those missing from the skeleton are ignored.
+    /// Translations and rotations are kept, scales are ignored
+    pub fn from_gltf(file_name: &str, mesh: &Mesh) -> Result<Vec<AnimationClip>, MeshParseError> {
+        let (document, buffers) = load_document(file_name)?;
+        let parents = node_parents(&document);
+        let skeleton: HashMap<usize, &Bone> = document
+            .nodes()
+            .filter_map(|node| {
+                let name = node_name(&node);
+                let bone = mesh.bones().iter().find(|bone| bone.name() == name)?;
+                Some((node.index(), bone))
+            })
+            .collect();
+
+        let mut clips = vec![];
+        for animation in document.animations() {
+            let name = match animation.name() {
+                Some(name) => name.into(),
+                None => format!("animation{}", animation.index()),
+            };
+            let mut translations: HashMap<usize, Channel<Vec3>> = HashMap::new();
+            let mut rotations: HashMap<usize, Channel<Quat>> = HashMap::new();
+            for channel in animation.channels() {
+                let node = channel.target().node().index();
+                if !skeleton.contains_key(&node) {
+                    continue;
+                }
+                let reader = channel.reader(|buffer| Some(&buffers[buffer.index()]));
+                let inputs: Vec<f32> = match reader.read_inputs() {
+                    Some(inputs) => inputs.collect(),
+                    None => continue,
+                };
+                match (channel.target().property(), reader.read_outputs()) {
+                    (Property::Translation, Some(ReadOutputs::Translations(outputs))) => {
+                        let outputs = outputs.map(Vec3::from).collect();
+                        translations.insert(node, read_channel(&channel, inputs, outputs));
+                    }
+                    (Property::Rotation, Some(ReadOutputs::Rotations(outputs))) => {
+                        let outputs = outputs.into_f32().map(Quat::from_array).collect();
+                        rotations.insert(node, read_channel(&channel, inputs, outputs));
+                    }
+                    _ => {}
+                }
+            }
+
+            let mut clip = AnimationClip::new(&name);
+            for (&node, bone) in &skeleton {
+                let (_, rest_rotation, rest_translation) = match document.nodes().nth(node) {
+                    Some(node) => local_transform(&node).to_scale_rotation_translation(),
+                    None => continue,
+                };
+                // nodes between a bone and its parent bone are folded into its keys
+                let parent_space = ancestors_transform(&document, &parents, node, |idx| skeleton.contains_key(&idx));
+                let translation = translations.get(&node);
+                let rotation = rotations.get(&node);
+                let mut times: Vec<f32> = translation
+                    .iter()
+                    .flat_map(|channel| channel.keys.iter().map(|(time, _)| *time))
+                    .chain(rotation.iter().flat_map(|channel| channel.keys.iter().map(|(time, _)| *time)))
+                    .collect();
+                times.sort_by(f32::total_cmp);
+                times.dedup();
+                if times.is_empty() {
+                    times.push(0.);
+                }
+                for time in times {
+                    let translation = translation
+                        .and_then(|channel| channel.sample(time, Vec3::lerp))
+                        .unwrap_or(rest_translation);
+                    let rotation = rotation
+                        .and_then(|channel| channel.sample(time, Quat::slerp))
+                        .unwrap_or(rest_rotation);
+                    let local = parent_space * Mat4::from_rotation_translation(rotation, translation);
+                    let (_, rotation, translation) = local.to_scale_rotation_translation();
+                    clip.add_keyframe(bone, BoneKeyframe { time, translation, rotation });
+                }
+            }
+            clips.push(clip);
+        }
+        Ok(clips)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// a skinned triangle moved by two joints, and a scaled copy of it held by a translated node,
+    /// with the buffer embedded as a data uri
+    const SCENE: &str = r#"{
+        "asset": { "version": "2.0" },
+        "scene": 0,
+        "scenes": [{ "nodes": [0, 1, 4] }],
+        "nodes": [
+            { "name": "body", "mesh": 0, "skin": 0, "translation": [5, 0, 0] },
+            { "name": "root", "children": [2] },
+            { "name": "arm", "translation": [0, 1, 0] },
+            { "name": "prop", "mesh": 1, "scale": [2, 2, 2] },
+            { "name": "holder", "translation": [0, 0, 2], "children": [3] }
+        ],
+        "meshes": [
+            { "primitives": [{ "attributes": { "POSITION": 0, "JOINTS_0": 2, "WEIGHTS_0": 3 }, "indices": 1, "material": 0 }] },
+            { "primitives": [{ "attributes": { "POSITION": 0 }, "indices": 1, "material": 1 }] }
+        ],
+        "skins": [{ "joints": [1, 2], "inverseBindMatrices": 4 }],
+        "materials": [
+            { "name": "skin", "pbrMetallicRoughness": { "baseColorFactor": [1, 0, 0, 1], "metallicFactor": 0, "roughnessFactor": 0.5 } },
+            { "name": "glass", "pbrMetallicRoughness": { "baseColorTexture": { "index": 0 } } }
+        ],
+        "textures": [{ "source": 0 }],
+        "images": [{ "uri": "data:image/png;base64,iVBORw0KGgo=" }],
+        "animations": [{
+            "name": "wave",
+            "channels": [{ "sampler": 0, "target": { "node": 2, "path": "translation" } }],
+            "samplers": [{ "input": 5, "output": 6 }]
+        }],
+        "accessors": [
+            { "bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3", "min": [0, 0, 0], "max": [1, 1, 0] },
+            { "bufferView": 1, "componentType": 5123, "count": 3, "type": "SCALAR" },
+            { "bufferView": 2, "componentType": 5121, "count": 3, "type": "VEC4" },
+            { "bufferView": 3, "componentType": 5126, "count": 3, "type": "VEC4" },
+            { "bufferView": 4, "componentType": 5126, "count": 2, "type": "MAT4" },
+            { "bufferView": 5, "componentType": 5126, "count": 2, "type": "SCALAR", "min": [0], "max": [1] },
+            { "bufferView": 6, "componentType": 5126, "count": 2, "type": "VEC3" }
+        ],
+        "bufferViews": [
+            { "buffer": 0, "byteOffset": 0, "byteLength": 36 },
+            { "buffer": 0, "byteOffset": 36, "byteLength": 6 },
+            { "buffer": 0, "byteOffset": 44, "byteLength": 12 },
+            { "buffer": 0, "byteOffset": 56, "byteLength": 48 },
+            { "buffer": 0, "byteOffset": 104, "byteLength": 128 },
+            { "buffer": 0, "byteOffset": 232, "byteLength": 8 },
+            { "buffer": 0, "byteOffset": 240, "byteLength": 24 }
+        ],
+        "buffers": [{
+            "byteLength": 264,
+            "uri": "data:application/octet-stream;base64,AAAAAAAAAAAAAAAAAACAPwAAAAAAAAAAAAAAAAAAgD8AAAAAAAABAAIAAAAAAQAAAAAAAAEAAAAAAAA/AAAAPwAAAAAAAAAAAACAPwAAAAAAAAAAAAAAAAAAgD8AAAAAAAAAAAAAAAAAAIA/AAAAAAAAAAAAAAAAAAAAAAAAgD8AAAAAAAAAAAAAAAAAAAAAAACAPwAAAAAAAAAAAAAAAAAAAAAAAIA/AACAPwAAAAAAAAAAAAAAAAAAAAAAAIA/AAAAAAAAAAAAAAAAAAAAAAAAgD8AAAAAAAAAAAAAgL8AAAAAAACAPwAAAAAAAIA/AAAAAAAAgD8AAAAAAAAAAAAAQEAAAAAA"
+        }]
+    }"#;
+
+    /// Load the scene and its animations from a temporary .gltf file, named after the test to run them in parallel
+    fn load_scene(test: &str) -> (Mesh, Vec<AnimationClip>) {
+        let path = std::env::temp_dir().join(format!("rend_ox_{}_{}.gltf", test, std::process::id()));
+        std::fs::write(&path, SCENE).unwrap();
+        let file_name = path.to_string_lossy().into_owned();
+        let mesh = Mesh::from_gltf(&file_name);
+        let clips = mesh.as_ref().ok().map(|mesh| AnimationClip::from_gltf(&file_name, mesh));
+        std::fs::remove_file(&path).unwrap();
+        match (mesh, clips) {
+            (Ok(mesh), Some(Ok(clips))) => (mesh, clips),
+            (Err(why), _) | (_, Some(Err(why))) => panic!("{}", why),
+            (Ok(_), None) => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn nodes_place_their_meshes() {
+        let (mesh, _) = load_scene("nodes");
+        assert_eq!(mesh.faces, [0, 1, 2, 3, 4, 5]);
+        // the skinned triangle stays in its bind pose, the other one follows its node and its parent
+        assert_eq!(&mesh.vertices[..3], [Vec3::ZERO, Vec3::X, Vec3::Y]);
+        assert_eq!(&mesh.vertices[3..], [Vec3::new(0., 0., 2.), Vec3::new(2., 0., 2.), Vec3::new(0., 2., 2.)]);
+        assert!(mesh.normals.iter().all(|normal| *normal == Vec3::Z));
+        assert_eq!(mesh.groups, [(0, "body".into()), (3, "prop".into())]);
+    }
+
+    #[test]
+    fn skins_become_bones_and_weights() {
+        let (mesh, _) = load_scene("skins");
+        let bones: Vec<_> = mesh.bones().iter().map(|bone| (bone.name(), bone.parent())).collect();
+        assert_eq!(bones, [("root", None), ("arm", Some(0))]);
+        assert_eq!(mesh.bones()[1].pose(), Mat4::from_translation(Vec3::Y));
+
+        assert_eq!(mesh.weights().len(), 6);
+        assert_eq!(mesh.weights()[0], [(0, 0.5), (1, 0.5)]);
+        assert_eq!(mesh.weights()[1], [(0, 1.)]);
+        assert_eq!(mesh.weights()[2], [(1, 1.)]);
+        assert!(mesh.weights()[3..].iter().all(|weights| weights.is_empty()));
+    }
+
+    #[test]
+    fn materials_are_read_from_pbr_parameters() {
+        let (mesh, _) = load_scene("materials");
+        assert_eq!(mesh.materials, [(0, Some("skin".into())), (3, Some("glass".into()))]);
+        let skin = &mesh.material_sources["skin"];
+        assert_eq!(skin.data.color, Vec4::new(1., 0., 0., 1.));
+        assert_eq!(skin.data.specular, Vec4::new(0.04, 0.04, 0.04, 0.5));
+
+        // images embedded as data uris are left out
+        assert!(!mesh.material_sources["glass"].map_bytes.contains_key(&DIFFUSE_MAP));
+        assert_eq!(mesh.warnings().len(), 1);
+        assert!(matches!(mesh.warnings()[0].kind, MeshParseErrorKind::UnsupportedFeature(_)));
+    }
+
+    #[test]
+    fn animations_move_the_matching_bones() {
+        let (_, clips) = load_scene("animations");
+        assert_eq!(clips.len(), 1);
+        let clip = &clips[0];
+        assert_eq!((clip.name.as_str(), clip.duration), ("wave", 1.));
+
+        let keyframes = clip.keyframes(1);
+        assert_eq!(keyframes.iter().map(|keyframe| keyframe.time).collect::<Vec<_>>(), [0., 1.]);
+        assert_eq!(keyframes[0].translation, Vec3::Y);
+        assert_eq!(keyframes[1].translation, Vec3::new(0., 3., 0.));
+        assert_eq!(clip.sample(1, 0.5).map(|(translation, _)| translation), Some(Vec3::new(0., 2., 0.)));
+        // bones without channels hold their rest pose
+        assert_eq!(clip.sample(0, 0.5), Some((Vec3::ZERO, Quat::IDENTITY)));
+    }
+}
@@ -0,0 +1,516 @@
+//! glTF 2.0 exporter
+//!
+//! Writes meshes, their materials and the nodes placing them as a binary .glb file
+
+use std::collections::HashMap;
+use std::io::Write;
+use std::path::Path;
+
+use glam::Mat4;
+use serde_json::{json, Value};
+
+use crate::material::{default_map, MaterialDescriptor, DIFFUSE_MAP, NORMAL_MAP};
+use crate::nannou::image;
+use crate::Vec3;
+
+use super::Mesh;
+
+const GLB_MAGIC: u32 = 0x4654_6C67;
+const JSON_CHUNK: u32 = 0x4E4F_534A;
+const BIN_CHUNK: u32 = 0x004E_4942;
+
+const FLOAT: u32 = 5126;
+const UNSIGNED_INT: u32 = 5125;
+const ARRAY_BUFFER: u32 = 34962;
+const ELEMENT_ARRAY_BUFFER: u32 = 34963;
+/// primitive mode drawing each index as a point
+const POINTS: u32 = 0;
+
+/// A range of faces of a mesh, given by its first and last index, and the material drawing it
+type Part = (u32, u32, Option<usize>);
+
+/// A scene to export as glTF
+///
+/// meshes are added once, and placed any number of times by nodes:
+/// ```ignore
+/// let mut scene = GltfScene::new();
+/// let ball = scene.add_mesh(&mesh);
+/// scene.add_node(ball, Mat4::from_translation(Vec3::new(0., 2., 0.)));
+/// scene.write_glb(&mut std::fs::File::create("scene.glb")?)?;
+/// ```
+pub struct GltfScene<'a> {
+    meshes: Vec<(&'a Mesh, Vec<Part>)>,
+    materials: Vec<(String, MaterialDescriptor)>,
+    nodes: Vec<(usize, Mat4)>,
+}
+
+/// binary chunk being built, with the buffer views and accessors pointing into it
+struct BinaryBuffer {
+    bytes: Vec<u8>,
+    views: Vec<Value>,
+    accessors: Vec<Value>,
+}
+
+impl BinaryBuffer {
+    /// append data as a new buffer view with a single accessor, and give the accessor index
+    fn push(&mut self, bytes: Vec<u8>, target: Option<u32>, accessor: Value) -> usize {
+        let mut view = json!({
+            "buffer": 0,
+            "byteOffset": self.bytes.len(),
+            "byteLength": bytes.len(),
+        });
+        if let Some(target) = target {
+            view["target"] = json!(target);
+        }
+        self.bytes.extend(bytes);
+        while !self.bytes.len().is_multiple_of(4) {
+            self.bytes.push(0);
+        }
+        self.views.push(view);
+        let mut accessor = accessor;
+        accessor["bufferView"] = json!(self.views.len() - 1);
+        self.accessors.push(accessor);
+        self.accessors.len() - 1
+    }
+
+    /// append data as a new buffer view, for images
+    fn push_view(&mut self, bytes: &[u8]) -> usize {
+        self.views.push(json!({
+            "buffer": 0,
+            "byteOffset": self.bytes.len(),
+            "byteLength": bytes.len(),
+        }));
+        self.bytes.extend(bytes);
+        while !self.bytes.len().is_multiple_of(4) {
+            self.bytes.push(0);
+        }
+        self.views.len() - 1
+    }
+
+    fn push_floats(&mut self, values: &[f32], count: usize, kind: &str, target: Option<u32>) -> usize {
+        let bytes = values.iter().flat_map(|value| value.to_le_bytes()).collect();
+        self.push(bytes, target, json!({ "componentType": FLOAT, "count": count, "type": kind }))
+    }
+}
+
+fn mesh_name(mesh: &Mesh) -> String {
+    Path::new(&mesh.path)
+        .file_stem()
+        .map_or(mesh.path.clone(), |stem| stem.to_string_lossy().into())
+}
+
+/// mime type of an encoded image told by its signature, `None` for the formats glTF doesn't allow
+fn image_mime_type(bytes: &[u8]) -> Option<&'static str> {
+    if bytes.starts_with(b"\x89PNG\r\n\x1a\n") {
+        Some("image/png")
+    } else if bytes.starts_with(&[0xFF, 0xD8, 0xFF]) {
+        Some("image/jpeg")
+    } else {
+        None
+    }
+}
+
+/// re-encode an image of any format the renderer can load as png
+fn to_png(bytes: &[u8]) -> std::io::Result<Vec<u8>> {
+    let invalid = |why| std::io::Error::new(std::io::ErrorKind::InvalidData, why);
+    let image = image::load_from_memory(bytes).map_err(invalid)?;
+    let mut png = vec![];
+    image.write_to(&mut png, image::ImageOutputFormat::Png).map_err(invalid)?;
+    Ok(png)
+}
+
+/// Inverse of the mapping done by the importer: specular colors close to the diffuse one are metallic,
+/// and the shininess gives the roughness
+fn metallic_factor(material: &MaterialDescriptor) -> f32 {
+    let color = material.data.color.truncate() - Vec3::splat(0.04);
+    let specular = material.data.specular.truncate() - Vec3::splat(0.04);
+    match color.length_squared() {
+        length if length > f32::EPSILON => (specular.dot(color) / length).clamp(0., 1.),
+        _ => 0.,
+    }
+}
+
+impl<'a> GltfScene<'a> {
+    pub fn new() -> GltfScene<'a> {
+        GltfScene {
+            meshes: vec![],
+            materials: vec![],
+            nodes: vec![],
+        }
+    }
+
+    /// Add a mesh with the materials it was loaded with, and give its index for [`Self::add_node()`]
+    pub fn add_mesh(&mut self, mesh: &'a Mesh) -> usize {
+        let mut parts = vec![];
+        for (i, (start, name)) in mesh.materials.iter().enumerate() {
+            let end = match mesh.materials.get(i + 1) {
+                Some((next, _)) => *next,
+                None => mesh.faces.len() as u32,
+            };
+            let material = name
+                .as_deref()
+                .and_then(|name| Some(self.add_material(name, mesh.material_sources.get(name)?)));
+            parts.push((*start, end, material));
+        }
+        if parts.is_empty() {
+            parts.push((0, mesh.faces.len() as u32, None));
+        }
+        self.add_mesh_parts(mesh, parts)
+    }
+
+    /// Add a mesh drawn entirely with a single material, and give its index for [`Self::add_node()`]
+    pub fn add_mesh_with_material(&mut self, mesh: &'a Mesh, name: &str, material: &MaterialDescriptor) -> usize {
+        let material = self.add_material(name, material);
+        self.add_mesh_parts(mesh, vec![(0, mesh.faces.len() as u32, Some(material))])
+    }
+
+    /// Add a mesh split in ranges of faces, each drawn with a material given by [`Self::add_material()`]
+    pub(crate) fn add_mesh_parts(&mut self, mesh: &'a Mesh, parts: Vec<Part>) -> usize {
+        self.meshes.push((mesh, parts));
+        self.meshes.len() - 1
+    }
+
+    /// Add a material, those sharing a name are only written once
+    pub(crate) fn add_material(&mut self, name: &str, material: &MaterialDescriptor) -> usize {
+        if let Some(idx) = self.materials.iter().position(|(other, _)| other == name) {
+            return idx;
+        }
+        self.materials.push((name.into(), material.clone()));
+        self.materials.len() - 1
+    }
+
+    /// Place a mesh given by [`Self::add_mesh()`] in the scene, and give the index of the node
+    ///
+    /// `None` when `mesh` wasn't given by this scene, no node being added
+    pub fn add_node(&mut self, mesh: usize, transform: Mat4) -> Option<usize> {
+        if mesh >= self.meshes.len() {
+            return None;
+        }
+        self.nodes.push((mesh, transform));
+        Some(self.nodes.len() - 1)
+    }
+
+    /// attributes of some vertices of a mesh
+    fn write_attributes(&self, buffer: &mut BinaryBuffer, mesh: &Mesh, vertices: &[usize]) -> Value {
+        let count = vertices.len();
+        let (min, max) = vertices.iter().fold(
+            (Vec3::splat(f32::MAX), Vec3::splat(f32::MIN)),
+            |(min, max), &vertex| (min.min(mesh.vertices[vertex]), max.max(mesh.vertices[vertex])),
+        );
+        let positions: Vec<f32> = vertices.iter().flat_map(|&vertex| mesh.vertices[vertex].to_array()).collect();
+        let position = buffer.push(
+            positions.iter().flat_map(|value| value.to_le_bytes()).collect(),
+            Some(ARRAY_BUFFER),
+            json!({
+                "componentType": FLOAT,
+                "count": count,
+                "type": "VEC3",
+                "min": min.to_array(),
+                "max": max.to_array(),
+            }),
+        );
+        let mut attributes = json!({ "POSITION": position });
+        if mesh.normals.len() == mesh.vertices.len() {
+            let normals: Vec<f32> = vertices.iter().flat_map(|&vertex| mesh.normals[vertex].to_array()).collect();
+            attributes["NORMAL"] = json!(buffer.push_floats(&normals, count, "VEC3", Some(ARRAY_BUFFER)));
+        }
+        if mesh.uvs.len() == mesh.vertices.len() {
+            // glTF uvs start from the top of the image
+            let uvs: Vec<f32> = vertices
+                .iter()
+                .flat_map(|&vertex| [mesh.uvs[vertex].x, 1. - mesh.uvs[vertex].y])
+                .collect();
+            attributes["TEXCOORD_0"] = json!(buffer.push_floats(&uvs, count, "VEC2", Some(ARRAY_BUFFER)));
+        }
+        if mesh.tangents.len() == mesh.vertices.len() {
+            let tangents: Vec<f32> = vertices.iter().flat_map(|&vertex| mesh.tangents[vertex].to_array()).collect();
+            attributes["TANGENT"] = json!(buffer.push_floats(&tangents, count, "VEC4", Some(ARRAY_BUFFER)));
+        }
+        if mesh.colors.len() == mesh.vertices.len() {
+            let colors: Vec<f32> = vertices.iter().flat_map(|&vertex| mesh.colors[vertex].to_array()).collect();
+            attributes["COLOR_0"] = json!(buffer.push_floats(&colors, count, "VEC4", Some(ARRAY_BUFFER)));
+        }
+        attributes
+    }
+
+    /// each part becomes a primitive holding only the vertices it uses, `None` when every part is empty
+    fn write_mesh(&self, buffer: &mut BinaryBuffer, mesh: &Mesh, parts: &[Part]) -> Option<Value> {
+        let primitives: Vec<Value> = parts
+            .iter()
+            .filter(|(start, end, _)| end > start)
+            .map(|(start, end, material)| {
+                let mut vertices = vec![];
+                let mut remap: HashMap<u32, u32> = HashMap::new();
+                let indices: Vec<u32> = mesh.faces[*start as usize..*end as usize]
+                    .iter()
+                    .map(|&index| {
+                        *remap.entry(index).or_insert_with(|| {
+                            vertices.push(index as usize);
+                            vertices.len() as u32 - 1
+                        })
+                    })
+                    .collect();
+                let attributes = self.write_attributes(buffer, mesh, &vertices);
+                let indices = buffer.push(
+                    indices.iter().flat_map(|index| index.to_le_bytes()).collect(),
+                    Some(ELEMENT_ARRAY_BUFFER),
+                    json!({ "componentType": UNSIGNED_INT, "count": indices.len(), "type": "SCALAR" }),
+                );
+                let mut primitive = json!({ "attributes": attributes, "indices": indices });
+                if mesh.point_cloud {
+                    primitive["mode"] = json!(POINTS);
+                }
+                if let Some(material) = material {
+                    primitive["material"] = json!(material);
+                }
+                primitive
+            })
+            .collect();
+        if primitives.is_empty() {
+            return None;
+        }
+        Some(json!({ "name": mesh_name(mesh), "primitives": primitives }))
+    }
+
+    /// texture of a map slot, embedded in the binary chunk, `None` for unset slots
+    ///
+    /// images other than png and jpeg are converted to png,
+    /// fails when the texture file can't be read or decoded
+    fn write_texture(&self, buffer: &mut BinaryBuffer, images: &mut Vec<Value>, material: &MaterialDescriptor, slot: usize) -> std::io::Result<Option<usize>> {
+        let Some(path) = material.maps.get(slot) else {
+            return Ok(None);
+        };
+        let bytes = match material.map_bytes.get(&slot) {
+            Some(bytes) => bytes.to_vec(),
+            None if path == default_map(slot) => return Ok(None),
+            None => std::fs::read(path)?,
+        };
+        let (bytes, mime_type) = match image_mime_type(&bytes) {
+            Some(mime_type) => (bytes, mime_type),
+            None => (to_png(&bytes)?, "image/png"),
+        };
+        let view = buffer.push_view(&bytes);
+        images.push(json!({ "bufferView": view, "mimeType": mime_type }));
+        Ok(Some(images.len() - 1))
+    }
+
+    fn write_material(&self, buffer: &mut BinaryBuffer, images: &mut Vec<Value>, name: &str, material: &MaterialDescriptor) -> std::io::Result<Value> {
+        let data = &material.data;
+        let mut pbr = json!({
+            "baseColorFactor": data.color.to_array(),
+            "metallicFactor": metallic_factor(material),
+            "roughnessFactor": (1. - data.specular.w).clamp(0., 1.),
+        });
+        let mut value = json!({
+            "name": name,
+            "extensions": {
+                "KHR_materials_specular": { "specularColorFactor": data.specular.truncate().to_array() },
+            },
+        });
+        if let Some(texture) = self.write_texture(buffer, images, material, DIFFUSE_MAP)? {
+            pbr["baseColorTexture"] = json!({ "index": texture });
+        }
+        if let Some(texture) = self.write_texture(buffer, images, material, NORMAL_MAP)? {
+            value["normalTexture"] = json!({ "index": texture });
+        }
+        value["pbrMetallicRoughness"] = pbr;
+        Ok(value)
+    }
+
+    /// Write the scene as a binary glTF, every buffer and texture being embedded
+    ///
+    /// materials keep their color, specular color and shininess, and their diffuse and normal maps,
+    /// a texture file that can't be read failing the export.
+    /// Specular maps hold a color where glTF expects metalness and roughness, they are left out.
+    /// Meshes without any face are left out, along with the nodes placing them
+    pub fn write_glb<W: Write>(&self, writer: &mut W) -> std::io::Result<()> {
+        let mut buffer = BinaryBuffer {
+            bytes: vec![],
+            views: vec![],
+            accessors: vec![],
+        };
+        let written: Vec<Option<Value>> = self
+            .meshes
+            .iter()
+            .map(|(mesh, parts)| self.write_mesh(&mut buffer, mesh, parts))
+            .collect();
+        // index of each mesh in the document, once the empty ones are left out
+        let mut mesh_indices = vec![None; written.len()];
+        let mut meshes = vec![];
+        for (idx, mesh) in written.into_iter().enumerate() {
+            if let Some(mesh) = mesh {
+                mesh_indices[idx] = Some(meshes.len());
+                meshes.push(mesh);
+            }
+        }
+        let mut images = vec![];
+        let materials: Vec<Value> = self
+            .materials
+            .iter()
+            .map(|(name, material)| self.write_material(&mut buffer, &mut images, name, material))
+            .collect::<std::io::Result<_>>()?;
+        let textures: Vec<Value> = (0..images.len()).map(|image| json!({ "source": image })).collect();
+        let nodes: Vec<Value> = self
+            .nodes
+            .iter()
+            .filter_map(|(mesh, transform)| {
+                Some(json!({
+                    "name": mesh_name(self.meshes[*mesh].0),
+                    "mesh": mesh_indices[*mesh]?,
+                    "matrix": transform.to_cols_array(),
+                }))
+            })
+            .collect();
+
+        let mut document = json!({
+            "asset": { "version": "2.0", "generator": "rend_ox" },
+            "scene": 0,
+            "scenes": [{ "nodes": (0..nodes.len()).collect::<Vec<_>>() }],
+        });
+        // glTF forbids empty arrays, they are left out
+        let arrays = [
+            ("nodes", nodes),
+            ("meshes", meshes),
+            ("materials", materials),
+            ("images", images),
+            ("textures", textures),
+            ("accessors", buffer.accessors),
+            ("bufferViews", buffer.views),
+        ];
+        for (key, array) in arrays {
+            if !array.is_empty() {
+                document[key] = json!(array);
+            }
+        }
+        if !self.materials.is_empty() {
+            document["extensionsUsed"] = json!(["KHR_materials_specular"]);
+        }
+        if !buffer.bytes.is_empty() {
+            document["buffers"] = json!([{ "byteLength": buffer.bytes.len() }]);
+        }
+
+        let mut json_chunk = serde_json::to_vec(&document)?;
+        while !json_chunk.len().is_multiple_of(4) {
+            json_chunk.push(b' ');
+        }
+        let length = 12 + 8 + json_chunk.len() + 8 + buffer.bytes.len();
+        writer.write_all(&GLB_MAGIC.to_le_bytes())?;
+        writer.write_all(&2u32.to_le_bytes())?;
+        writer.write_all(&(length as u32).to_le_bytes())?;
+        writer.write_all(&(json_chunk.len() as u32).to_le_bytes())?;
+        writer.write_all(&JSON_CHUNK.to_le_bytes())?;
+        writer.write_all(&json_chunk)?;
+        writer.write_all(&(buffer.bytes.len() as u32).to_le_bytes())?;
+        writer.write_all(&BIN_CHUNK.to_le_bytes())?;
+        writer.write_all(&buffer.bytes)
+    }
+}
+
+impl Default for GltfScene<'_> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Mesh {
+    /// Write the mesh and the materials it was loaded with as a binary glTF, see [`GltfScene`]
+    pub fn write_glb<W: Write>(&self, writer: &mut W) -> std::io::Result<()> {
+        let mut scene = GltfScene::new();
+        let mesh = scene.add_mesh(self);
+        scene.add_node(mesh, Mat4::IDENTITY);
+        scene.write_glb(writer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::SPECULAR_MAP;
+
+    fn quad() -> Mesh {
+        let positions = vec![Vec3::ZERO, Vec3::X, Vec3::Y, Vec3::new(1., 1., 0.)];
+        Mesh::from_buffers(vec![0, 1, 2, 2, 1, 3], positions, vec![], vec![]).unwrap()
+    }
+
+    /// Write a scene to a temporary .glb file named after the test and load it back
+    fn round_trip(test: &str, scene: &GltfScene) -> Mesh {
+        let path = std::env::temp_dir().join(format!("rend_ox_{}_{}.glb", test, std::process::id()));
+        scene.write_glb(&mut std::fs::File::create(&path).unwrap()).unwrap();
+        let mesh = Mesh::from_gltf(&path.to_string_lossy());
+        std::fs::remove_file(&path).unwrap();
+        match mesh {
+            Ok(mesh) => mesh,
+            Err(why) => panic!("{}", why),
+        }
+    }
+
+    fn face_positions(mesh: &Mesh) -> Vec<Vec3> {
+        mesh.faces.iter().map(|&index| mesh.vertices[index as usize]).collect()
+    }
+
+    #[test]
+    fn meshes_load_back() {
+        let mesh = quad();
+        let mut scene = GltfScene::new();
+        let idx = scene.add_mesh(&mesh);
+        scene.add_node(idx, Mat4::IDENTITY);
+        let loaded = round_trip("meshes", &scene);
+        assert_eq!(loaded.vertices, mesh.vertices);
+        assert_eq!(loaded.faces, mesh.faces);
+        assert_eq!(loaded.normals, mesh.normals);
+    }
+
+    #[test]
+    fn materials_load_back() {
+        let mut mesh = quad();
+        mesh.materials = vec![(0, Some("red".into())), (3, Some("blue".into()))];
+        for name in ["red", "blue"] {
+            mesh.material_sources.insert(name.into(), MaterialDescriptor::new());
+        }
+        let mut scene = GltfScene::new();
+        let idx = scene.add_mesh(&mesh);
+        scene.add_node(idx, Mat4::from_translation(Vec3::Z));
+        let loaded = round_trip("materials", &scene);
+
+        assert_eq!(loaded.materials, [(0, Some("red".into())), (3, Some("blue".into()))]);
+        let moved: Vec<Vec3> = face_positions(&mesh).iter().map(|position| *position + Vec3::Z).collect();
+        assert_eq!(face_positions(&loaded), moved);
+    }
+
+    #[test]
+    fn textures_are_png_or_jpeg() {
+        let mut bmp = vec![];
+        let checker = image::RgbImage::from_fn(2, 2, |x, y| image::Rgb([255 * ((x + y) % 2) as u8; 3]));
+        image::DynamicImage::ImageRgb8(checker)
+            .write_to(&mut bmp, image::ImageOutputFormat::Bmp)
+            .unwrap();
+        let mut material = MaterialDescriptor::new();
+        material.set_map_bytes(DIFFUSE_MAP, "checker.bmp", &bmp);
+        material.set_map_bytes(SPECULAR_MAP, "checker.bmp", &bmp);
+
+        let mesh = quad();
+        let mut scene = GltfScene::new();
+        let idx = scene.add_mesh_with_material(&mesh, "checker", &material);
+        scene.add_node(idx, Mat4::IDENTITY);
+        let loaded = round_trip("textures", &scene);
+
+        let checker = &loaded.material_sources["checker"];
+        assert_eq!(image_mime_type(&checker.map_bytes[&DIFFUSE_MAP]), Some("image/png"));
+        // specular maps have no glTF equivalent
+        assert!(!checker.map_bytes.contains_key(&SPECULAR_MAP));
+        assert_eq!(image_mime_type(&bmp), None);
+    }
+
+    #[test]
+    fn empty_meshes_are_left_out() {
+        let (empty, mesh) = (Mesh::new(), quad());
+        let mut scene = GltfScene::new();
+        let empty = scene.add_mesh(&empty);
+        let idx = scene.add_mesh(&mesh);
+        scene.add_node(empty, Mat4::IDENTITY);
+        scene.add_node(idx, Mat4::IDENTITY);
+        let loaded = round_trip("empty", &scene);
+        assert_eq!(loaded.faces, mesh.faces);
+        assert_eq!(loaded.groups.len(), 1);
+    }
+}
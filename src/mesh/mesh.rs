@@ -3,26 +3,54 @@
 //! Represents any mesh data loaded from any format
 //! Supports:
 //!     - custom normal splits
-//!     - vertex groups
+//!     - object and group splits
 //!     - material slots
 //! planned support for bone animation
 
-use crate::error::RendError;
-use crate::mesh::{Indices, Normals, Vertices};
+use crate::error::{MeshParseError, MeshParseErrorKind, RendError};
+use crate::mesh::{Aabb, BoundingSphere, Indices, Normals, Vertices};
 use crate::Vec3;
+use crate::wgpu;
 use std::cmp::Ordering;
+use std::io::BufRead;
+use std::path::Path;
 
+use crate::mesh::mtl_parser::MaterialLibrary;
 use crate::mesh::obj_parser::OBJMesh;
-use glam::Mat4;
+use crate::mesh::solver::{generate_tangents, solve_indices, unique_indices};
+use glam::{Mat4, Vec4};
 
 /// A Bone used for animation
 /// Not fully implemented, format subject to change
 #[derive(Clone, Debug)]
-pub(crate) struct Bone {
+pub struct Bone {
     pub(crate) idx: u32,
-    pub(crate) _pose: Mat4,
+    pub(crate) name: String,
+    /// reference pose, relative to the parent bone
+    pub(crate) pose: Mat4,
     // pub(crate) rest: Mat4,
-    pub(crate) _parent: i32,
+    /// index of the parent bone, -1 for roots
+    pub(crate) parent: i32,
+}
+
+impl Bone {
+    pub fn idx(&self) -> u32 {
+        self.idx
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// index of the parent bone, `None` for roots
+    pub fn parent(&self) -> Option<u32> {
+        u32::try_from(self.parent).ok()
+    }
+
+    /// reference pose, relative to the parent bone
+    pub fn pose(&self) -> Mat4 {
+        self.pose
+    }
 }
 
 impl PartialEq for Bone {
@@ -43,7 +71,10 @@ pub(crate) struct Triangle {
     pub(crate) normals: Option<[usize; 3]>,
     pub(crate) calculated_normal: usize,
     pub(crate) textures: Option<[usize; 3]>,
-    pub(crate) _group: Option<u32>,
+    pub(crate) material: Option<usize>,
+    pub(crate) group: Option<u32>,
+    /// smoothing group used when generating normals, flat shaded when `None`
+    pub(crate) smoothing: Option<u32>,
 }
 
 impl Triangle {
@@ -60,7 +91,9 @@ impl Triangle {
             normals: Some([3; 3]),
             calculated_normal: 0,
             textures: None,
-            _group: None,
+            material: None,
+            group: None,
+            smoothing: None,
         }
     }
 }
@@ -70,18 +103,42 @@ impl Triangle {
 /// holds geometry data
 /// The [`Self::buffers()`] Method gives access to the internal buffers for use in rendering
 /// A mesh can be loaded from:
-///     - an ascii obj file with [`Self::from_obj()`]
-///     - ~~an smd file with [`Self::from_smd()`]~~ Not yet supported
+///     - an ascii obj file with [`Self::from_obj()`], or from memory with [`Self::from_obj_reader()`] and [`Self::from_obj_bytes()`]
+///     - an ascii Valve smd reference mesh with [`Self::from_smd()`], along with its skeleton and weights
+///     - a glTF 2.0 .gltf or .glb file with [`Self::from_gltf()`], along with its skins and materials
+///     - an ascii or binary stl file with [`Self::from_stl()`], or from memory with [`Self::from_stl_bytes()`]
+///     - an ascii or binary ply file with [`Self::from_ply()`], along with its vertex colors, faceless files giving point clouds
+///     - buffers generated at runtime with [`Self::from_buffers()`] or a [`super::MeshBuilder`]
+///     - a parametric shape from [`super::primitives`], such as a cube or a sphere
+///     - a binary rmesh cache with [`Self::load_binary()`], written by [`Self::save_binary()`]
+///     - any of the above with [`Self::from_file()`], picking the format from the extension
 pub struct Mesh {
     pub(crate) path: String,
     pub(crate) faces: Indices,
     pub(crate) vertices: Vertices,
     pub(crate) normals: Normals,
     pub(crate) uvs: Vertices,
-    pub(crate) _weights: Vec<Vec<(u32, f32)>>,
-    pub(crate) _materials: Vec<Option<String>>,
-    pub(crate) _groups: Vec<(u32, String)>,
-    pub(crate) _bones: Vec<Bone>,
+    /// tangent of each vertex, `w` giving the handedness of the bitangent, empty if not loaded
+    pub(crate) tangents: Vec<Vec4>,
+    /// rgba color of each vertex, empty if not loaded
+    pub(crate) colors: Vec<Vec4>,
+    /// faces list each vertex once, to be drawn as points
+    pub(crate) point_cloud: bool,
+    /// box and sphere around `vertices`, updated whenever they change
+    pub(crate) aabb: Aabb,
+    pub(crate) bounding_sphere: BoundingSphere,
+    /// bones influencing each vertex, with their weight
+    pub(crate) weights: Vec<Vec<(u32, f32)>>,
+    /// material of each range of `faces`, given by its first index and lasting until the next one
+    pub(crate) materials: Vec<(u32, Option<String>)>,
+    pub(crate) material_sources: MaterialLibrary,
+    /// named ranges of `faces` (objects and groups), given by their first index and lasting until the next one
+    pub(crate) groups: Vec<(u32, String)>,
+    pub(crate) bones: Vec<Bone>,
+    /// files read along `path` while loading, such as material libraries, textures excluded
+    pub(crate) dependencies: Vec<String>,
+    /// non fatal issues found while loading
+    pub(crate) warnings: Vec<MeshParseError>,
 }
 
 impl Mesh {
@@ -92,10 +149,18 @@ impl Mesh {
             vertices: vec![],
             uvs: vec![],
             normals: vec![],
-            _materials: vec![],
-            _groups: vec![],
-            _weights: vec![],
-            _bones: vec![],
+            tangents: vec![],
+            colors: vec![],
+            point_cloud: false,
+            aabb: Aabb::default(),
+            bounding_sphere: BoundingSphere::default(),
+            materials: vec![],
+            material_sources: MaterialLibrary::new(),
+            groups: vec![],
+            weights: vec![],
+            bones: vec![],
+            dependencies: vec![],
+            warnings: vec![],
         }
     }
 
@@ -105,33 +170,242 @@ impl Mesh {
         (&self.faces, &self.vertices, &self.uvs, &self.normals)
     }
 
-    /// Load an obj from a ASCII .smd file
-    /// This function will return an error on an unparsable file
+    /// The skeleton of a rigged mesh, sorted by bone index
+    pub fn bones(&self) -> &[Bone] {
+        &self.bones
+    }
+
+    /// Tangent of each vertex of [`Self::buffers()`], with the handedness of its bitangent in `w`
+    ///
+    /// empty when the source file doesn't provide them and [`Self::generate_tangents()`] wasn't called
+    pub fn tangents(&self) -> &[Vec4] {
+        &self.tangents
+    }
+
+    /// Compute MikkTSpace style tangents from the positions, normals and uvs, replacing the loaded ones
+    ///
+    /// meshes without tangents get them when loaded by the app, for normal mapping.
+    /// Point clouds have no faces to follow and are left untouched
+    pub fn generate_tangents(&mut self) {
+        if self.point_cloud {
+            return;
+        }
+        self.tangents = generate_tangents(&self.vertices, &self.uvs, &self.normals, &self.faces);
+    }
+
+    /// Color of each vertex of [`Self::buffers()`], empty when the source file doesn't provide them
+    pub fn colors(&self) -> &[Vec4] {
+        &self.colors
+    }
+
+    /// Whether the mesh is a set of points without faces, its indices listing each vertex once
+    pub fn is_point_cloud(&self) -> bool {
+        self.point_cloud
+    }
+
+    /// The box around the vertices of the mesh, in model space
+    pub fn aabb(&self) -> Aabb {
+        self.aabb
+    }
+
+    /// The sphere around the vertices of the mesh, in model space
+    pub fn bounding_sphere(&self) -> BoundingSphere {
+        self.bounding_sphere
+    }
+
+    /// Fit the bounding volumes to the vertices, once they are loaded or changed
+    pub(crate) fn update_bounds(&mut self) {
+        self.aabb = Aabb::from_points(&self.vertices);
+        self.bounding_sphere = BoundingSphere::from_points(&self.vertices);
+    }
+
+    /// Non fatal issues found while loading, such as material libraries or statements that couldn't be read
+    ///
+    /// the mesh is still usable, the parts concerned being left out
+    pub fn warnings(&self) -> &[MeshParseError] {
+        &self.warnings
+    }
+
+    /// Bones influencing each vertex of [`Self::buffers()`], with their weight
+    pub fn weights(&self) -> &[Vec<(u32, f32)>] {
+        &self.weights
+    }
+
+    /// Merge the vertices whose positions lie within `epsilon` of each other,
+    /// as long as they share the same uv and normal
+    ///
+    /// this closes seams left by exporters duplicating positions, the order of faces is kept.
+    /// Point clouds are left untouched.
+    /// This function will return an error when `epsilon` isn't a positive finite number
+    pub fn weld(&mut self, epsilon: f32) -> Result<(), RendError> {
+        if !(epsilon.is_finite() && epsilon > 0.) {
+            return Err(RendError::new("the weld distance must be a positive finite number"));
+        }
+        self.weld_within(epsilon);
+        Ok(())
+    }
+
+    /// Merge the vertices lying within `epsilon` of each other, only identical ones for a zero `epsilon`
+    pub(crate) fn weld_within(&mut self, epsilon: f32) {
+        if self.point_cloud {
+            return;
+        }
+        let uvs = unique_indices(&self.uvs);
+        let normals = unique_indices(&self.normals);
+        let triangles: Vec<Triangle> = self
+            .faces
+            .chunks_exact(3)
+            .map(|face| {
+                let corners = [face[0] as usize, face[1] as usize, face[2] as usize];
+                let mut triangle = Triangle::new();
+                triangle.points = corners;
+                triangle.textures = Some(corners.map(|corner| uvs[corner]));
+                triangle.normals = Some(corners.map(|corner| normals[corner]));
+                triangle
+            })
+            .collect();
+        let (vertices, uvs, normals, faces, sources) =
+            solve_indices(&self.vertices, &self.uvs, &self.normals, &triangles, Some(epsilon));
+        if !self.weights.is_empty() {
+            self.weights = sources.iter().map(|&src| self.weights[src].clone()).collect();
+        }
+        if !self.tangents.is_empty() {
+            self.tangents = sources.iter().map(|&src| self.tangents[src]).collect();
+        }
+        if !self.colors.is_empty() {
+            self.colors = sources.iter().map(|&src| self.colors[src]).collect();
+        }
+        self.vertices = vertices;
+        self.uvs = uvs;
+        self.normals = normals;
+        self.faces = faces.iter().map(|x| *x as u32).collect();
+        self.update_bounds();
+    }
+
+    /// The smallest index format able to address every vertex of the mesh
+    pub(crate) fn index_format(&self) -> wgpu::IndexFormat {
+        if self.vertices.len() <= u16::MAX as usize + 1 {
+            wgpu::IndexFormat::Uint16
+        } else {
+            wgpu::IndexFormat::Uint32
+        }
+    }
+
+    /// The primitives formed by the indices of the mesh
+    pub(crate) fn topology(&self) -> wgpu::PrimitiveTopology {
+        if self.point_cloud {
+            wgpu::PrimitiveTopology::PointList
+        } else {
+            wgpu::PrimitiveTopology::TriangleList
+        }
+    }
+
+    /// The color of each vertex, white when the mesh has none
+    pub(crate) fn color_buffer(&self) -> Vec<Vec4> {
+        if self.colors.len() == self.vertices.len() {
+            self.colors.clone()
+        } else {
+            vec![Vec4::ONE; self.vertices.len()]
+        }
+    }
+
+    /// The tangent of each vertex, generated when the mesh has none, along `X` for point clouds
+    pub(crate) fn tangent_buffer(&self) -> Vec<Vec4> {
+        if self.tangents.len() == self.vertices.len() {
+            self.tangents.clone()
+        } else if self.point_cloud {
+            vec![Vec4::new(1., 0., 0., 1.); self.vertices.len()]
+        } else {
+            generate_tangents(&self.vertices, &self.uvs, &self.normals, &self.faces)
+        }
+    }
+
+    /// The four strongest bone influences of each vertex, as joint indices and normalized weights
     ///
-    /// Not yet implemented, do not use
-    #[deprecated(since="0.0.0", note="smd files not yet supported, please use `from_obj` instead")]
-    pub fn from_smd(_file_name: &str) -> Result<Mesh, Box<dyn std::error::Error>> {
-        Err(Box::new(RendError::new("SMD Not Supported!")))
+    /// vertices without any influence follow the bone 0 entirely
+    pub(crate) fn skin_buffers(&self) -> (Vec<[u32; 4]>, Vec<[f32; 4]>) {
+        let mut joints = Vec::with_capacity(self.vertices.len());
+        let mut weights = Vec::with_capacity(self.vertices.len());
+        for i in 0..self.vertices.len() {
+            let mut influences = self.weights.get(i).cloned().unwrap_or_default();
+            influences.sort_by(|a, b| b.1.total_cmp(&a.1));
+            influences.truncate(4);
+            let total: f32 = influences.iter().map(|(_, weight)| weight).sum();
+            let mut vertex_joints = [0; 4];
+            let mut vertex_weights = [0.; 4];
+            if total > 0. {
+                for (j, (bone, weight)) in influences.iter().enumerate() {
+                    vertex_joints[j] = *bone;
+                    vertex_weights[j] = weight / total;
+                }
+            } else {
+                vertex_weights[0] = 1.;
+            }
+            joints.push(vertex_joints);
+            weights.push(vertex_weights);
+        }
+        (joints, weights)
+    }
+
+    /// Load a mesh from a file, its format being picked from the file extension
+    pub fn from_file(file_name: &str) -> Result<Mesh, MeshParseError> {
+        let extension = Path::new(file_name)
+            .extension()
+            .map(|extension| extension.to_string_lossy().to_lowercase());
+        match extension.as_deref() {
+            Some("obj") => Self::from_obj(file_name),
+            Some("smd") => Self::from_smd(file_name),
+            Some("gltf") | Some("glb") => Self::from_gltf(file_name),
+            Some("stl") => Self::from_stl(file_name),
+            Some("ply") => Self::from_ply(file_name),
+            Some("rmesh") => Self::load_binary(file_name),
+            _ => Err(MeshParseError::new(file_name, 0, 0, MeshParseErrorKind::UnsupportedFormat)),
+        }
     }
 
     /// Load an obj from a ASCII .obj file
-    /// This function will return an error on an unparsable file
-    pub fn from_obj(file_name: &str) -> Result<Mesh, Box<dyn std::error::Error>> {
+    /// This function will return an error locating the issue on an unparsable file
+    pub fn from_obj(file_name: &str) -> Result<Mesh, MeshParseError> {
         let mut obj = OBJMesh::new();
-        if !obj.load_obj(file_name) {
-            return Err(Box::new(RendError::new("Failed to parse")));
-        }
-        let (faces, vertices, uvs, normals) = obj.as_buffers();
-        Ok(Mesh {
-            path: file_name.into(),
+        obj.load_obj(file_name)?;
+        Ok(Self::from_obj_mesh(obj, file_name))
+    }
+
+    /// Load an obj from any reader over ASCII obj data
+    ///
+    /// `name` identifies the mesh, material libraries are looked up relative to it
+    pub fn from_obj_reader<R: BufRead>(reader: R, name: &str) -> Result<Mesh, MeshParseError> {
+        let mut obj = OBJMesh::new();
+        obj.load_obj_reader(reader, name)?;
+        Ok(Self::from_obj_mesh(obj, name))
+    }
+
+    /// Load an obj from ASCII obj data in memory, such as an `include_bytes!` asset
+    pub fn from_obj_bytes(bytes: &[u8], name: &str) -> Result<Mesh, MeshParseError> {
+        Self::from_obj_reader(bytes, name)
+    }
+
+    fn from_obj_mesh(obj: OBJMesh, path: &str) -> Mesh {
+        let (faces, vertices, uvs, normals, colors) = obj.as_buffers();
+        let (aabb, bounding_sphere) = (Aabb::from_points(&vertices), BoundingSphere::from_points(&vertices));
+        Mesh {
+            path: path.into(),
             faces,
             vertices,
             uvs,
             normals,
-            _materials: vec![],
-            _groups: vec![],
-            _weights: vec![],
-            _bones: vec![],
-        })
+            tangents: vec![],
+            colors,
+            point_cloud: false,
+            aabb,
+            bounding_sphere,
+            materials: obj.material_ranges(),
+            groups: obj.group_ranges(),
+            material_sources: obj.library,
+            weights: vec![],
+            bones: vec![],
+            dependencies: obj.libraries,
+            warnings: obj.warnings,
+        }
     }
 }
@@ -1,15 +1,34 @@
-//! Utilities to load 3D files, currently the only supported format is obj
+//! Utilities to load and save 3D files, supports obj, Valve smd reference meshes, glTF 2.0, stl and ply,
+//! along with a binary cache format
 
 mod mesh;
 mod obj_parser;
+mod obj_writer;
+mod smd_parser;
+mod gltf_parser;
+mod gltf_writer;
+mod stl_parser;
+mod stl_writer;
+mod ply_parser;
+mod rmesh;
+mod builder;
+mod bounds;
+pub mod primitives;
+mod mtl_parser;
 mod descriptor;
 mod solver;
 
 pub(crate) use crate::Vec3;
 
+pub use bounds::{Aabb, BoundingSphere};
+pub use builder::MeshBuilder;
+pub use primitives::Primitive;
 pub use descriptor::MeshDescriptor;
+pub use gltf_writer::GltfScene;
+pub use stl_writer::StlFormat;
+pub(crate) use descriptor::MeshPart;
 pub use mesh::*;
 
 pub(crate) type Vertices = Vec<Vec3>;
-pub(crate) type Indices = Vec<u16>;
+pub(crate) type Indices = Vec<u32>;
 pub(crate) type Normals = Vec<Vec3>;
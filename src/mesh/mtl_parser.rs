@@ -0,0 +1,154 @@
+//! MTL material library parser
+//!
+//! Turns the materials referenced by an obj `mtllib` statement into [`MaterialDescriptor`]s
+
+use std::collections::HashMap;
+use std::fs::OpenOptions;
+use std::io::{BufRead, BufReader};
+use std::path::Path;
+
+use crate::error::{MeshParseError, MeshParseErrorKind};
+use crate::glam::Vec4;
+use crate::material::{MaterialDescriptor, DIFFUSE_MAP, NORMAL_MAP, SPECULAR_MAP};
+
+use super::obj_parser::{column, LineResult};
+
+pub(crate) type MaterialLibrary = HashMap<String, MaterialDescriptor>;
+
+fn parse_scalar(line: &str, args: &[&str], i: usize) -> LineResult<f32> {
+    match args.get(i) {
+        Some(arg) => arg
+            .parse::<f32>()
+            .map_err(|_| (column(line, arg), MeshParseErrorKind::InvalidNumber)),
+        None => Err((line.len() + 1, MeshParseErrorKind::MissingValue)),
+    }
+}
+
+fn parse_color(line: &str, args: &[&str]) -> LineResult<[f32; 3]> {
+    Ok([
+        parse_scalar(line, args, 0)?,
+        parse_scalar(line, args, 1)?,
+        parse_scalar(line, args, 2)?,
+    ])
+}
+
+/// Texture statements may carry options before the file name (`map_Bump -bm 0.5 normal.png`),
+/// the path is resolved relative to the library
+fn parse_map(line: &str, args: &[&str], dir: &Path) -> LineResult<String> {
+    match args.last() {
+        Some(file) => Ok(dir.join(file).to_string_lossy().into_owned()),
+        None => Err((line.len() + 1, MeshParseErrorKind::MissingValue)),
+    }
+}
+
+/// Load every material of an ASCII .mtl file
+///
+/// Unknown statements are ignored, as most of them (illumination models, reflection maps...)
+/// have no equivalent in a [`MaterialDescriptor`].
+/// A malformed statement is skipped and pushed to `warnings`, keeping the rest of its material,
+/// only a library that can't be read fails
+pub(crate) fn load_mtl(file_name: &Path, warnings: &mut Vec<MeshParseError>) -> Result<MaterialLibrary, MeshParseError> {
+    let path = file_name.to_string_lossy();
+    let file = OpenOptions::new()
+        .read(true)
+        .open(file_name)
+        .map_err(|why| MeshParseError::new(&path, 0, 0, MeshParseErrorKind::Io(why)))?;
+    let dir = file_name.parent().unwrap_or(Path::new(""));
+    let mut library = MaterialLibrary::new();
+    let mut current: Option<(String, MaterialDescriptor)> = None;
+
+    for (line_idx, option_line) in BufReader::new(file).lines().enumerate() {
+        let line = option_line
+            .map_err(|why| MeshParseError::new(&path, line_idx + 1, 0, MeshParseErrorKind::Io(why)))?;
+        let words: Vec<&str> = line.split_ascii_whitespace().collect();
+        let (statement, args) = match words.split_first() {
+            Some((statement, _)) if statement.starts_with('#') => continue,
+            Some((statement, args)) => (*statement, args),
+            None => continue,
+        };
+
+        if statement == "newmtl" {
+            if let Some((name, material)) = current.take() {
+                library.insert(name, material);
+            }
+            current = Some((args.join(" "), MaterialDescriptor::new()));
+            continue;
+        }
+        let Some((_, material)) = current.as_mut() else {
+            continue;
+        };
+        if let Err((column, kind)) = parse_statement(&line, statement, args, dir, material) {
+            warnings.push(MeshParseError::new(&path, line_idx + 1, column, kind));
+        }
+    }
+    if let Some((name, material)) = current {
+        library.insert(name, material);
+    }
+    Ok(library)
+}
+
+fn parse_statement(
+    line: &str,
+    statement: &str,
+    args: &[&str],
+    dir: &Path,
+    material: &mut MaterialDescriptor,
+) -> LineResult<()> {
+    let data = &mut material.data;
+    match statement {
+        "Kd" => {
+            let [r, g, b] = parse_color(line, args)?;
+            data.color = Vec4::new(r, g, b, data.color.w);
+        }
+        "Ks" => {
+            let [r, g, b] = parse_color(line, args)?;
+            data.specular = Vec4::new(r, g, b, data.specular.w);
+        }
+        // shininess goes up to 1000, the shaders expect it normalized
+        "Ns" => data.specular.w = (parse_scalar(line, args, 0)? / 1000.).clamp(0., 1.),
+        "d" => data.color.w = parse_scalar(line, args, 0)?,
+        "Tr" => data.color.w = 1. - parse_scalar(line, args, 0)?,
+        "map_Kd" => material.set_map(DIFFUSE_MAP, &parse_map(line, args, dir)?),
+        "map_Bump" | "map_bump" | "bump" | "norm" => {
+            material.set_map(NORMAL_MAP, &parse_map(line, args, dir)?)
+        }
+        "map_Ks" => material.set_map(SPECULAR_MAP, &parse_map(line, args, dir)?),
+        _ => {}
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn malformed_statements_are_skipped() {
+        let dir = std::env::temp_dir().join(format!("rend_ox_mtl_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("paint.mtl");
+        let mtl = "# paints\nnewmtl red\nKd 1 x 0\nKs 0.5 0.5 0.5\nNs 500\nd\nmap_Kd red.png\n\nnewmtl blue\nKd 0 0 1\n";
+        std::fs::write(&path, mtl).unwrap();
+
+        let mut warnings = vec![];
+        let library = load_mtl(&path, &mut warnings).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let red = &library["red"];
+        assert_eq!(red.data.color, MaterialDescriptor::new().data.color);
+        assert_eq!(red.data.specular, Vec4::new(0.5, 0.5, 0.5, 0.5));
+        assert_eq!(red.maps[DIFFUSE_MAP], dir.join("red.png").to_string_lossy());
+        assert_eq!(library["blue"].data.color.truncate(), crate::Vec3::Z);
+
+        let located: Vec<_> = warnings.iter().map(|warning| (warning.line, warning.column)).collect();
+        assert_eq!(located, [(3, 6), (6, 2)]);
+        assert!(matches!(warnings[0].kind, MeshParseErrorKind::InvalidNumber));
+        assert!(matches!(warnings[1].kind, MeshParseErrorKind::MissingValue));
+    }
+
+    #[test]
+    fn missing_libraries_fail() {
+        let error = load_mtl(Path::new("missing.mtl"), &mut vec![]).unwrap_err();
+        assert!(matches!(error.kind, MeshParseErrorKind::Io(_)));
+    }
+}
@@ -1,18 +1,38 @@
 use crate::Vec3;
+use glam::{Vec2, Vec4};
 
 use std::fs::OpenOptions;
 use std::io::{BufRead, BufReader};
+use std::path::Path;
 
-use super::solver::solve_indices;
+use crate::error::{MeshParseError, MeshParseErrorKind};
+
+use super::mtl_parser::{load_mtl, MaterialLibrary};
+use super::solver::{generate_normals, solve_indices};
 use super::{Indices, Normals, Triangle, Vertices};
 
+/// Name given to the faces declared before any `o` or `g` statement
+const DEFAULT_GROUP: &str = "default";
+
 pub struct OBJMesh {
     pub(crate) triangles: Vec<Triangle>,
     pub(crate) vertices: Vertices,
     pub(crate) uvs: Vertices,
     pub(crate) normals: Normals,
+    /// color following the position of each vertex, if any
+    pub(crate) colors: Vec<Option<Vec4>>,
     pub(crate) calculated: Normals,
-    pub(crate) _materials: Vec<Option<String>>,
+    pub(crate) materials: Vec<String>,
+    pub(crate) library: MaterialLibrary,
+    /// paths of the material libraries referenced by `mtllib` statements
+    pub(crate) libraries: Vec<String>,
+    pub(crate) groups: Vec<String>,
+    /// non fatal issues, such as material libraries or statements that couldn't be loaded
+    pub(crate) warnings: Vec<MeshParseError>,
+    current_material: Option<usize>,
+    current_object: Option<String>,
+    current_group: Option<u32>,
+    current_smoothing: Option<u32>,
 }
 
 impl OBJMesh {
@@ -21,281 +41,246 @@ impl OBJMesh {
             triangles: vec![],
             vertices: vec![],
             normals: vec![],
+            colors: vec![],
             calculated: vec![],
             uvs: vec![],
-            _materials: vec![],
+            materials: vec![],
+            library: MaterialLibrary::new(),
+            libraries: vec![],
+            groups: vec![],
+            warnings: vec![],
+            current_material: None,
+            current_object: None,
+            current_group: None,
+            current_smoothing: None,
         }
     }
 
-    pub fn as_buffers(&self) -> (Indices, Vertices, Vertices, Normals) {
-        let (vp, uv, nm, faces) =
-            solve_indices(&self.vertices, &self.uvs, &self.normals, &self.triangles);
-        (faces.iter().map(|x| *x as u16).collect(), vp, uv, nm)
-    }
-
-    pub fn load_obj(&mut self, file_name: &str) -> bool {
-        let file = OpenOptions::new().read(true).open(file_name);
-
-        if let Ok(obj) = file {
-            for option_line in BufReader::new(obj).lines() {
-                match option_line {
-                    Err(why) => panic!("{:?}", why),
-                    Ok(line) => match line {
-                        s if s.chars().all(|x| x.is_ascii_whitespace()) => {
-                            continue;
-                        }
-                        s if s.starts_with('#') => {
-                            continue;
-                        }
-                        s if s.starts_with("o ") => {
-                            continue;
-                        }
-                        s if s.starts_with("s ") => {
-                            continue;
-                        }
-                        s if s.starts_with("usemtl ") => {
-                            continue;
-                        }
-                        s if s.starts_with("mtllib ") => {
-                            continue;
-                        }
-                        s if s.starts_with("v ") => {
-                            if let Some(vertex) = self.parse_vertex(s) {
-                                self.vertices.push(vertex);
-                            } else {
-                                println!("Invalid vertexes in \"{}\" !", file_name);
-                                return false;
-                            }
-                        }
-                        s if s.starts_with("vt ") => {
-                            if let Some(vertex) = self.parse_uvw(s) {
-                                self.uvs.push(vertex);
-                            } else {
-                                println!("Invalid uv in \"{}\" !", file_name);
-                                return false;
-                            }
-                        }
-                        s if s.starts_with("vn ") => {
-                            if let Some(normal) = self.parse_normal(s) {
-                                self.normals.push(normal);
-                            } else {
-                                println!("Invalid normal in \"{}\" !", file_name);
-                                return false;
-                            }
-                        }
-                        s if s.starts_with("f ") => {
-                            if !self.parse_face(s) {
-                                println!("Invalid face in \"{}\" !", file_name);
-                                return false;
-                            }
-                        }
-                        other => {
-                            println!("Invalid \"{file_name}\" mesh file!, \"{other}\" string not supported");
-                            return false;
-                        }
-                    },
-                }
-            }
+    /// Indices, positions, uvs and normals of the welded vertices,
+    /// followed by their colors when any vertex has one, the others being white
+    pub fn as_buffers(&self) -> (Indices, Vertices, Vertices, Normals, Vec<Vec4>) {
+        let (vp, uv, nm, faces, sources) =
+            solve_indices(&self.vertices, &self.uvs, &self.normals, &self.triangles, None);
+        let colors = if self.colors.iter().any(Option::is_some) {
+            sources.iter().map(|&src| self.colors[src].unwrap_or(Vec4::ONE)).collect()
         } else {
-            println!("Cant open \"{}\" mesh file!", file_name);
-        }
-        true
-    }
-
-    fn parse_face_point(&self, point: &str) -> Option<(usize, Option<usize>, Option<usize>)> {
-        let iter = &mut point.split('/');
-        let position: usize;
-        let normal: Option<usize>;
-        let texture: Option<usize>;
-
-        if let Some(str) = iter.next() {
-            match str.parse::<usize>() {
-                Ok(x) => {
-                    if self.vertices.len() >= x && x > 0 {
-                        position = x - 1
-                    } else {
-                        return None;
-                    }
-                }
-                Err(_) => return None,
-            };
-        } else {
-            return None;
-        }
+            vec![]
+        };
+        (faces.iter().map(|x| *x as u32).collect(), vp, uv, nm, colors)
+    }
 
-        if let Some(str) = iter.next() {
-            if str.is_empty() {
-                return Some((position, None, None));
-            } else {
-                match str.parse::<usize>() {
-                    Ok(x) => {
-                        if self.uvs.len() >= x && x > 0 {
-                            texture = Some(x - 1);
-                        } else {
-                            return None;
-                        }
-                    }
-                    Err(_) => return None,
-                }
+    /// Split the indices returned by [`Self::as_buffers()`] into ranges of faces sharing a key,
+    /// each given by its first index and lasting until the next one
+    fn ranges<T: PartialEq + Copy>(&self, key: impl Fn(&Triangle) -> T) -> Vec<(u32, T)> {
+        let mut ranges: Vec<(u32, T)> = vec![];
+        for (i, triangle) in self.triangles.iter().enumerate() {
+            let value = key(triangle);
+            if ranges.last().is_none_or(|(_, last)| *last != value) {
+                ranges.push(((i * 3) as u32, value));
             }
-        } else {
-            return None;
         }
+        ranges
+    }
 
-        if let Some(str) = iter.next() {
-            if str.is_empty() {
-                return Some((position, None, None));
-            } else {
-                match str.parse::<usize>() {
-                    Ok(x) => {
-                        if self.normals.len() >= x && x > 0 {
-                            normal = Some(x - 1);
-                        } else {
-                            return None;
-                        }
-                    }
-                    Err(_) => return None,
-                }
-            }
-        } else {
-            return None;
+    /// Ranges of the indices returned by [`Self::as_buffers()`] sharing the same material
+    pub fn material_ranges(&self) -> Vec<(u32, Option<String>)> {
+        self.ranges(|triangle| triangle.material)
+            .into_iter()
+            .map(|(start, slot)| (start, slot.map(|slot| self.materials[slot].clone())))
+            .collect()
+    }
+
+    /// Ranges of the indices returned by [`Self::as_buffers()`] belonging to the same object or group,
+    /// faces declared before any `o` or `g` statement form a `default` group, as long as there are other groups
+    pub fn group_ranges(&self) -> Vec<(u32, String)> {
+        if self.groups.is_empty() {
+            return vec![];
         }
-        Some((position, texture, normal))
+        self.ranges(|triangle| triangle.group)
+            .into_iter()
+            .map(|(start, group)| match group {
+                Some(group) => (start, self.groups[group as usize].clone()),
+                None => (start, DEFAULT_GROUP.into()),
+            })
+            .collect()
     }
 
-    fn parse_second_face(&mut self, fst_triangle: &Triangle, last_point: &str) -> Option<Triangle> {
-        let mut points: [usize; 3] = [fst_triangle.points[1], fst_triangle.points[2], 0];
-        let mut textures = match fst_triangle.textures {
-            Some(tex) => Some([tex[1], tex[2], 0]),
-            None => None,
-        };
-        let mut normals = match fst_triangle.textures {
-            Some(nor) => Some([nor[1], nor[2], 0]),
-            None => None,
-        };
+    pub fn load_obj(&mut self, file_name: &str) -> Result<(), MeshParseError> {
+        match OpenOptions::new().read(true).open(file_name) {
+            Ok(obj) => self.load_obj_reader(BufReader::new(obj), file_name),
+            Err(why) => Err(MeshParseError::new(file_name, 0, 0, MeshParseErrorKind::Io(why))),
+        }
+    }
 
-        if let Some(indexes) = self.parse_face_point(last_point) {
-            points[2] = indexes.0;
+    /// Parse obj data from any reader
+    ///
+    /// `file_name` is used in errors, and to find the material libraries relative to it
+    pub fn load_obj_reader<R: BufRead>(&mut self, reader: R, file_name: &str) -> Result<(), MeshParseError> {
+        for (line_idx, option_line) in reader.lines().enumerate() {
+            let line = option_line.map_err(|why| {
+                MeshParseError::new(file_name, line_idx + 1, 0, MeshParseErrorKind::Io(why))
+            })?;
+            self.parse_line(&line, file_name).map_err(|(column, kind)| {
+                MeshParseError::new(file_name, line_idx + 1, column, kind)
+            })?;
+        }
+        generate_normals(
+            &self.vertices,
+            &self.calculated,
+            &mut self.normals,
+            &mut self.triangles,
+        );
+        // keep each group contiguous, and faces sharing a material drawn together
+        self.triangles
+            .sort_by_key(|triangle| (triangle.group, triangle.material));
+        Ok(())
+    }
 
-            match indexes.1 {
-                Some(index) => {
-                    if let Some(ref mut point_texture) = textures {
-                        point_texture[2] = index;
-                    }
-                }
-                None => textures = None,
+    fn parse_line(&mut self, line: &str, file_name: &str) -> LineResult<()> {
+        match line.trim_start() {
+            s if s.is_empty() || s.starts_with('#') => {}
+            s if s.starts_with("o ") => {
+                let object = statement_argument(line, s)?;
+                self.current_object = Some(object.into());
+                self.use_group(object.into());
             }
-            match indexes.2 {
-                Some(index) => {
-                    if let Some(ref mut point_normal) = normals {
-                        point_normal[2] = index;
-                    }
-                }
-                None => normals = None,
+            s if s.starts_with("g ") => {
+                let group = statement_argument(line, s)?;
+                let name = match &self.current_object {
+                    Some(object) if object != group => format!("{object}/{group}"),
+                    _ => group.into(),
+                };
+                self.use_group(name);
             }
-        } else {
-            return None;
+            s if s.starts_with("s ") => {
+                self.current_smoothing = match statement_argument(line, s)? {
+                    "off" | "0" => None,
+                    group => match group.parse::<u32>() {
+                        Ok(group) => Some(group),
+                        Err(_) => return Err((column(line, group), MeshParseErrorKind::InvalidNumber)),
+                    },
+                };
+            }
+            s if s.starts_with("usemtl ") => {
+                let material = statement_argument(line, s)?;
+                self.use_material(material);
+            }
+            s if s.starts_with("mtllib ") => {
+                self.load_material_libraries(file_name, s);
+            }
+            s if s.starts_with("v ") => {
+                let vertex = parse_floats(line, s, 3, 3)?;
+                self.vertices.push(vertex);
+                self.colors.push(parse_vertex_color(line, s)?);
+            }
+            s if s.starts_with("vt ") => {
+                let uvw = parse_floats(line, s, 1, 3)?;
+                self.uvs.push(uvw);
+            }
+            s if s.starts_with("vn ") => {
+                let normal = parse_floats(line, s, 3, 3)?;
+                self.normals.push(normal.normalize());
+            }
+            s if s.starts_with("f ") => {
+                self.parse_face(line, s)?;
+            }
+            s => return Err((column(line, s), MeshParseErrorKind::UnsupportedStatement)),
         }
-        let calculated_normal = self.calculated.len();
-        let snd_triangle = Triangle {
-            points,
-            normals,
-            calculated_normal,
-            textures,
-            _group: None,
-        };
-        self.calculated
-            .push(self.normal_from_indexes(&snd_triangle));
-        Some(snd_triangle)
+        Ok(())
     }
 
-    fn parse_face(&mut self, line: String) -> bool {
-        let points: Vec<&str> = line.split_ascii_whitespace().skip(1).collect();
-        let len = points.len();
-        if len < 3 || len > 4 {
-            return false;
-        }
-        let mut fst_triangle = Triangle::new();
-        fst_triangle.normals = Some([0; 3]);
-        fst_triangle.textures = Some([0; 3]);
-        for i in 0..3 {
-            if let Some(point) = self.parse_face_point(points[i]) {
-                fst_triangle.points[i] = point.0;
-
-                if let Some(ref mut texture) = fst_triangle.textures {
-                    match point.1 {
-                        Some(valid_point) => texture[i] = valid_point,
-                        None => fst_triangle.textures = None,
-                    }
-                }
-                if let Some(ref mut normal) = fst_triangle.normals {
-                    match point.2 {
-                        Some(valid_point) => normal[i] = valid_point,
-                        None => fst_triangle.normals = None,
-                    }
-                }
-            } else {
-                return false;
+    /// Load the libraries of a `mtllib` line, their paths are relative to the obj file
+    ///
+    /// A library that can't be loaded isn't fatal, its materials are left unbound and the error kept as a warning
+    fn load_material_libraries(&mut self, file_name: &str, line: &str) {
+        let dir = Path::new(file_name).parent().unwrap_or(Path::new(""));
+        for library in line.split_ascii_whitespace().skip(1) {
+            let path = dir.join(library);
+            self.libraries.push(path.to_string_lossy().into_owned());
+            match load_mtl(&path, &mut self.warnings) {
+                Ok(materials) => self.library.extend(materials),
+                Err(why) => self.warnings.push(why),
             }
         }
-        fst_triangle.calculated_normal = self.calculated.len();
+    }
 
-        self.calculated
-            .push(self.normal_from_indexes(&fst_triangle));
-        if len == 4 {
-            if let Some(snd_face) = self.parse_second_face(&fst_triangle, points[3]) {
-                self.triangles.push(snd_face);
-            } else {
-                return false;
+    fn use_group(&mut self, name: String) {
+        let slot = match self.groups.iter().position(|group| *group == name) {
+            Some(slot) => slot,
+            None => {
+                self.groups.push(name);
+                self.groups.len() - 1
             }
-        }
-        self.triangles.push(fst_triangle);
-        true
+        };
+        self.current_group = Some(slot as u32);
     }
 
-    fn parse_vertex(&mut self, line: String) -> Option<Vec3> {
-        let mut new_vertex: Vec3 = Vec3::ZERO;
-        let mut iter = line.split_ascii_whitespace().filter(|&x| !x.is_empty());
-
-        iter.next();
-        for i in 0..3 {
-            if let Some(point) = iter.next() {
-                if let Ok(point) = point.parse::<f32>() {
-                    new_vertex[i] = point as f32;
-                } else {
-                    return None;
-                };
-            } else {
-                return None;
+    fn use_material(&mut self, name: &str) {
+        let slot = match self.materials.iter().position(|material| material == name) {
+            Some(slot) => slot,
+            None => {
+                self.materials.push(name.into());
+                self.materials.len() - 1
             }
-        }
-        Some(new_vertex)
+        };
+        self.current_material = Some(slot);
     }
 
-    fn parse_uvw(&mut self, line: String) -> Option<Vec3> {
-        let mut new_vertex: Vec3 = Vec3::ZERO;
-        let mut iter = line.split_ascii_whitespace().filter(|&x| !x.is_empty());
+    /// Parse a `v`, `v/vt`, `v//vn` or `v/vt/vn` face corner
+    fn parse_face_point(&self, line: &str, point: &str) -> LineResult<(usize, Option<usize>, Option<usize>)> {
+        let mut iter = point.split('/');
 
-        iter.next();
-        for i in 0..3 {
-            if let Some(point) = iter.next() {
-                if let Ok(point) = point.parse::<f32>() {
-                    new_vertex[i] = point as f32;
-                } else {
-                    return None;
-                };
-            }
-        }
-        Some(new_vertex)
+        let position = match iter.next() {
+            Some(str) => resolve_index(line, str, self.vertices.len())?,
+            None => return Err((column(line, point), MeshParseErrorKind::MissingValue)),
+        };
+        let texture = match iter.next() {
+            None | Some("") => None,
+            Some(str) => Some(resolve_index(line, str, self.uvs.len())?),
+        };
+        let normal = match iter.next() {
+            None | Some("") => None,
+            Some(str) => Some(resolve_index(line, str, self.normals.len())?),
+        };
+        Ok((position, texture, normal))
     }
 
-    fn parse_normal(&mut self, line: String) -> Option<Vec3> {
-        let new_normal_asv = self.parse_vertex(line);
-        if let Some(normal_vertex) = new_normal_asv {
-            Some(Vec3::from(normal_vertex.normalize()))
-        } else {
-            None
+    fn parse_face(&mut self, line: &str, statement: &str) -> LineResult<()> {
+        let mut corners: Vec<(usize, Option<usize>, Option<usize>)> = vec![];
+        for point in statement.split_ascii_whitespace().skip(1) {
+            corners.push(self.parse_face_point(line, point)?);
         }
+        if corners.len() < 3 {
+            return Err((line.len() + 1, MeshParseErrorKind::MissingValue));
+        }
+        let has_textures = corners.iter().all(|corner| corner.1.is_some());
+        let has_normals = corners.iter().all(|corner| corner.2.is_some());
+        let polygon: Vec<Vec3> = corners
+            .iter()
+            .map(|corner| self.vertices[corner.0])
+            .collect();
+
+        for ear in triangulate(&polygon) {
+            let mut triangle = Triangle::new();
+            triangle.points = ear.map(|i| corners[i].0);
+            triangle.textures = if has_textures {
+                Some(ear.map(|i| corners[i].1.unwrap_or(0)))
+            } else {
+                None
+            };
+            triangle.normals = if has_normals {
+                Some(ear.map(|i| corners[i].2.unwrap_or(0)))
+            } else {
+                None
+            };
+            triangle.calculated_normal = self.calculated.len();
+            triangle.material = self.current_material;
+            triangle.group = self.current_group;
+            triangle.smoothing = self.current_smoothing;
+            self.calculated.push(self.normal_from_indexes(&triangle));
+            self.triangles.push(triangle);
+        }
+        Ok(())
     }
 
     fn normal_from_indexes(&self, triangle: &Triangle) -> Vec3 {
@@ -306,3 +291,330 @@ impl OBJMesh {
         )
     }
 }
+
+/// A parsing error, located by its column in the line
+pub(crate) type LineResult<T> = Result<T, (usize, MeshParseErrorKind)>;
+
+/// 1-based column of `token`, which must be a slice of `line`
+pub(crate) fn column(line: &str, token: &str) -> usize {
+    token.as_ptr() as usize - line.as_ptr() as usize + 1
+}
+
+/// The text following the keyword of a statement
+pub(crate) fn statement_argument<'a>(line: &str, statement: &'a str) -> LineResult<&'a str> {
+    match statement.split_once(char::is_whitespace) {
+        Some((_, argument)) if !argument.trim().is_empty() => Ok(argument.trim()),
+        _ => Err((line.len() + 1, MeshParseErrorKind::MissingValue)),
+    }
+}
+
+/// Parse between `min` and `max` numbers following the keyword of a statement,
+/// missing optional values are left to 0
+pub(crate) fn parse_floats(line: &str, statement: &str, min: usize, max: usize) -> LineResult<Vec3> {
+    let mut values = Vec3::ZERO;
+    let mut iter = statement.split_ascii_whitespace().skip(1);
+
+    for i in 0..max {
+        match iter.next() {
+            Some(value) => match value.parse::<f32>() {
+                Ok(value) => values[i] = value,
+                Err(_) => return Err((column(line, value), MeshParseErrorKind::InvalidNumber)),
+            },
+            None if i < min => return Err((line.len() + 1, MeshParseErrorKind::MissingValue)),
+            None => break,
+        }
+    }
+    Ok(values)
+}
+
+/// Parse the `r g b` color following the position of a `v x y z r g b` statement
+///
+/// a lone fourth value is the `w` coordinate of the position, not a color
+fn parse_vertex_color(line: &str, statement: &str) -> LineResult<Option<Vec4>> {
+    let Some(z) = statement.split_ascii_whitespace().nth(3) else {
+        return Ok(None);
+    };
+    // the z coordinate stands for the keyword skipped by `parse_floats`
+    let color = &statement[column(statement, z) - 1..];
+    if color.split_ascii_whitespace().count() < 4 {
+        return Ok(None);
+    }
+    Ok(Some(parse_floats(line, color, 3, 3)?.extend(1.)))
+}
+
+/// Resolve a 1-based OBJ index into a 0-based one
+///
+/// Negative indices are relative to the end of the `len` elements read so far,
+/// `-1` being the last one
+fn resolve_index(line: &str, index: &str, len: usize) -> LineResult<usize> {
+    match index.parse::<i64>() {
+        Ok(x) if x > 0 && x as usize <= len => Ok(x as usize - 1),
+        Ok(x) if x < 0 && x.unsigned_abs() as usize <= len => Ok(len - x.unsigned_abs() as usize),
+        Ok(_) => Err((column(line, index), MeshParseErrorKind::IndexOutOfRange)),
+        Err(_) => Err((column(line, index), MeshParseErrorKind::InvalidNumber)),
+    }
+}
+
+/// Twice the signed area of the 2D triangle (a, b, c), positive when counter-clockwise
+fn signed_area(a: Vec2, b: Vec2, c: Vec2) -> f32 {
+    (b - a).perp_dot(c - a)
+}
+
+fn point_in_triangle(point: Vec2, a: Vec2, b: Vec2, c: Vec2) -> bool {
+    signed_area(a, b, point) >= 0.
+        && signed_area(b, c, point) >= 0.
+        && signed_area(c, a, point) >= 0.
+}
+
+/// Whether the corner `cur` can be clipped, `min_area` being the area under which a triangle is degenerate
+fn is_ear(projected: &[Vec2], remaining: &[usize], min_area: f32, prev: usize, cur: usize, next: usize) -> bool {
+    let (a, b, c) = (projected[prev], projected[cur], projected[next]);
+    if signed_area(a, b, c) <= min_area {
+        return false;
+    }
+    !remaining
+        .iter()
+        .filter(|&&i| i != prev && i != cur && i != next)
+        .any(|&i| point_in_triangle(projected[i], a, b, c))
+}
+
+/// Split a polygon into triangles by ear clipping
+///
+/// The polygon is projected onto its best-fit plane (Newell's method), so concave
+/// faces are supported as long as they don't self-intersect.
+/// The returned triangles index into `polygon` and keep its winding order.
+pub(crate) fn triangulate(polygon: &[Vec3]) -> Vec<[usize; 3]> {
+    let len = polygon.len();
+    if len < 3 {
+        return vec![];
+    }
+    if len == 3 {
+        return vec![[0, 1, 2]];
+    }
+
+    let mut normal = Vec3::ZERO;
+    for i in 0..len {
+        let (cur, next) = (polygon[i], polygon[(i + 1) % len]);
+        normal.x += (cur.y - next.y) * (cur.z + next.z);
+        normal.y += (cur.z - next.z) * (cur.x + next.x);
+        normal.z += (cur.x - next.x) * (cur.y + next.y);
+    }
+    let normal = normal.normalize_or_zero();
+    if normal == Vec3::ZERO {
+        // degenerate polygon, no plane to project on
+        return (1..len - 1).map(|i| [0, i, i + 1]).collect();
+    }
+    let u_axis = normal.any_orthonormal_vector();
+    let v_axis = normal.cross(u_axis);
+    let projected: Vec<Vec2> = polygon
+        .iter()
+        .map(|point| Vec2::new(point.dot(u_axis), point.dot(v_axis)))
+        .collect();
+    // the degeneracy threshold follows the size of the polygon, so that small models triangulate like large ones
+    let (min, max) = projected
+        .iter()
+        .fold((Vec2::MAX, Vec2::MIN), |(min, max), point| (min.min(*point), max.max(*point)));
+    let min_area = (max - min).length_squared() * f32::EPSILON;
+
+    let mut remaining: Vec<usize> = (0..len).collect();
+    let mut triangles = Vec::with_capacity(len - 2);
+    let mut i = 0;
+    let mut attempts = 0;
+    while remaining.len() > 3 {
+        let count = remaining.len();
+        i %= count;
+        let prev = remaining[(i + count - 1) % count];
+        let cur = remaining[i];
+        let next = remaining[(i + 1) % count];
+        // a full loop without any ear means the polygon is self-intersecting
+        // or collinear, clip anyway so we always terminate
+        if attempts >= count || is_ear(&projected, &remaining, min_area, prev, cur, next) {
+            triangles.push([prev, cur, next]);
+            remaining.remove(i);
+            attempts = 0;
+        } else {
+            i += 1;
+            attempts += 1;
+        }
+    }
+    triangles.push([remaining[0], remaining[1], remaining[2]]);
+    triangles
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mesh::Mesh;
+
+    const TRIANGLE: &str = "v 0 0 0\nv 1 0 0\nv 0 1 0\n";
+
+    fn load(obj: &str) -> Mesh {
+        Mesh::from_obj_bytes(obj.as_bytes(), "test.obj").unwrap()
+    }
+
+    fn load_error(obj: &str) -> MeshParseError {
+        match Mesh::from_obj_bytes(obj.as_bytes(), "test.obj") {
+            Ok(_) => panic!("invalid obj data loaded"),
+            Err(why) => why,
+        }
+    }
+
+    fn face_positions(mesh: &Mesh) -> Vec<Vec3> {
+        mesh.faces.iter().map(|&index| mesh.vertices[index as usize]).collect()
+    }
+
+    /// Check that the triangles tile the counter-clockwise `polygon` lying in the XY plane, keeping its winding
+    fn assert_tiles(polygon: &[Vec3], triangles: &[[usize; 3]]) {
+        assert_eq!(triangles.len(), polygon.len() - 2);
+        let polygon_area: f32 = (0..polygon.len())
+            .map(|i| polygon[i].truncate().perp_dot(polygon[(i + 1) % polygon.len()].truncate()) / 2.)
+            .sum();
+        let mut area = 0.;
+        for [a, b, c] in triangles {
+            let normal = (polygon[*b] - polygon[*a]).cross(polygon[*c] - polygon[*a]);
+            assert!(normal.z > 0., "winding order must be kept");
+            area += normal.z / 2.;
+        }
+        assert!((area - polygon_area).abs() <= polygon_area * 1e-4, "{area} != {polygon_area}");
+    }
+
+    #[test]
+    fn ngons_are_triangulated() {
+        let mesh = load("v 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0\nf 1 2 3 4\n");
+        assert_eq!(mesh.faces.len(), 6);
+
+        // an arrow pointing up, concave at its bottom
+        let polygon = [
+            Vec3::new(0., 0., 0.),
+            Vec3::new(1., 1., 0.),
+            Vec3::new(2., 0., 0.),
+            Vec3::new(1., 3., 0.),
+        ];
+        assert_tiles(&polygon, &triangulate(&polygon));
+    }
+
+    #[test]
+    fn small_ngons_are_triangulated() {
+        // a U shape, concave at its top, down to millimetre scale
+        let shape = [(0., 0.), (3., 0.), (3., 3.), (2., 3.), (2., 1.), (1., 1.), (1., 3.), (0., 3.)];
+        for scale in [1., 1e-3, 1e-4] {
+            let polygon: Vec<Vec3> = shape.iter().map(|&(x, y)| Vec3::new(x, y, 0.) * scale).collect();
+            assert_tiles(&polygon, &triangulate(&polygon));
+        }
+    }
+
+    #[test]
+    fn indices_resolve_from_both_ends() {
+        assert_eq!(resolve_index("1", "1", 3).ok(), Some(0));
+        assert_eq!(resolve_index("3", "3", 3).ok(), Some(2));
+        assert_eq!(resolve_index("-1", "-1", 3).ok(), Some(2));
+        assert_eq!(resolve_index("-3", "-3", 3).ok(), Some(0));
+        for index in ["0", "4", "-4"] {
+            assert!(matches!(resolve_index(index, index, 3), Err((1, MeshParseErrorKind::IndexOutOfRange))));
+        }
+        assert!(matches!(resolve_index("a", "a", 3), Err((1, MeshParseErrorKind::InvalidNumber))));
+    }
+
+    #[test]
+    fn negative_indices_follow_the_vertices_read_so_far() {
+        let absolute = load(&format!("{TRIANGLE}f 1 2 3\nv 0 0 1\nv 1 0 1\nv 0 1 1\nf 4 5 6\n"));
+        let relative = load(&format!("{TRIANGLE}f -3 -2 -1\nv 0 0 1\nv 1 0 1\nv 0 1 1\nf -3 -2 -1\n"));
+        assert_eq!(face_positions(&relative), face_positions(&absolute));
+        assert_eq!(face_positions(&relative)[3..], [Vec3::Z, Vec3::new(1., 0., 1.), Vec3::new(0., 1., 1.)]);
+    }
+
+    #[test]
+    fn faces_are_split_by_material() {
+        let mesh = load(&format!("{TRIANGLE}f 1 2 3\nusemtl a\nf 1 2 3\nusemtl b\nf 1 2 3\nusemtl a\nf 1 2 3\n"));
+        assert_eq!(
+            mesh.materials,
+            vec![(0, None), (3, Some("a".to_string())), (9, Some("b".to_string()))]
+        );
+    }
+
+    #[test]
+    fn faces_are_split_by_object_and_group() {
+        let mesh = load(&format!("{TRIANGLE}f 1 2 3\no body\nf 1 2 3\ng arm\nf 1 2 3\ng body\nf 1 2 3\n"));
+        assert_eq!(
+            mesh.groups,
+            vec![(0, "default".to_string()), (3, "body".to_string()), (9, "body/arm".to_string())]
+        );
+
+        let mesh = load(&format!("{TRIANGLE}f 1 2 3\n"));
+        assert!(mesh.groups.is_empty());
+    }
+
+    /// normals of the vertices lying at `position`
+    fn normals_at(mesh: &Mesh, position: Vec3) -> Vec<Vec3> {
+        (0..mesh.vertices.len())
+            .filter(|&i| mesh.vertices[i] == position)
+            .map(|i| mesh.normals[i])
+            .collect()
+    }
+
+    #[test]
+    fn missing_normals_follow_smoothing_groups() {
+        // a floor facing up and a wall facing back over it, sharing their edge along X
+        let faces = |floor: &str, wall: &str| {
+            load(&format!(
+                "v 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0\nv 1 1 1\nv 0 1 1\ns {floor}\nf 1 2 3 4\ns {wall}\nf 4 3 5 6\n"
+            ))
+        };
+        let edge = Vec3::new(1., 1., 0.);
+
+        let smooth = faces("1", "1");
+        let shared = normals_at(&smooth, edge);
+        assert_eq!(shared.len(), 1);
+        assert!(shared[0].abs_diff_eq(Vec3::new(0., -1., 1.).normalize(), 1e-5));
+        assert_eq!(normals_at(&smooth, Vec3::ZERO), [Vec3::Z]);
+        assert_eq!(normals_at(&smooth, Vec3::ONE), [-Vec3::Y]);
+
+        for (floor, wall) in [("1", "2"), ("off", "off"), ("0", "1")] {
+            let split = faces(floor, wall);
+            let mut shared = normals_at(&split, edge);
+            shared.sort_by(|a, b| a.z.total_cmp(&b.z));
+            // flat normals are per triangle, so the corners of a face may repeat them
+            shared.dedup();
+            assert_eq!(shared, [-Vec3::Y, Vec3::Z], "s {floor} / s {wall}");
+            assert_eq!(split.faces.len(), 12);
+        }
+    }
+
+    #[test]
+    fn readers_load_like_bytes() {
+        let obj = format!("{TRIANGLE}f 1 2 3\n");
+        let reader = std::io::BufReader::new(std::io::Cursor::new(obj.clone().into_bytes()));
+        let from_reader = Mesh::from_obj_reader(reader, "memory.obj").unwrap();
+        let from_bytes = load(&obj);
+        assert_eq!(from_reader.faces, from_bytes.faces);
+        assert_eq!(from_reader.vertices, from_bytes.vertices);
+        assert_eq!(from_reader.path, "memory.obj");
+    }
+
+    #[test]
+    fn errors_are_located() {
+        let error = load_error("v 0 0 0\nv 1 x 0\n");
+        assert_eq!((error.file.as_str(), error.line, error.column), ("test.obj", 2, 5));
+        assert!(matches!(error.kind, MeshParseErrorKind::InvalidNumber));
+
+        let error = load_error(&format!("{TRIANGLE}\nf 1 2 9\n"));
+        assert_eq!((error.line, error.column), (5, 7));
+        assert!(matches!(error.kind, MeshParseErrorKind::IndexOutOfRange));
+
+        let error = load_error(&format!("{TRIANGLE}  l 1 2\n"));
+        assert_eq!((error.line, error.column), (4, 3));
+        assert!(matches!(error.kind, MeshParseErrorKind::UnsupportedStatement));
+
+        let error = load_error(&format!("{TRIANGLE}f 1 2\n"));
+        assert_eq!((error.line, error.column), (4, 6));
+        assert!(matches!(error.kind, MeshParseErrorKind::MissingValue));
+    }
+
+    #[test]
+    fn missing_material_libraries_are_warnings() {
+        let mesh = load(&format!("mtllib missing.mtl\n{TRIANGLE}usemtl a\nf 1 2 3\n"));
+        assert_eq!(mesh.faces.len(), 3);
+        assert_eq!(mesh.warnings().len(), 1);
+        assert!(matches!(mesh.warnings()[0].kind, MeshParseErrorKind::Io(_)));
+    }
+}
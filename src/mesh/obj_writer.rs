@@ -0,0 +1,99 @@
+//! OBJ exporter
+//!
+//! Writes the welded buffers of a [`Mesh`] back as an ASCII obj file, and its materials as a .mtl library
+
+use std::io::Write;
+
+use crate::material::{default_map, DIFFUSE_MAP, NORMAL_MAP, SPECULAR_MAP};
+
+use super::Mesh;
+
+/// Material used by the faces that had none, once a previous range set one
+const DEFAULT_MATERIAL: &str = "default";
+
+impl Mesh {
+    /// Write the mesh as ASCII obj data
+    ///
+    /// Every vertex is emitted with its uv and normal, so faces use the same index for all three,
+    /// and with its color as `v x y z r g b` when the mesh has some.
+    /// Group and material names are kept as `g` and `usemtl` statements, faces without a material
+    /// following some that have one using a `default` material.
+    /// `material_library` is referenced by a `mtllib` statement, see [`Self::write_mtl()`] to write it.
+    /// Point clouds only have their vertices written
+    pub fn write_obj<W: Write>(&self, writer: &mut W, material_library: Option<&str>) -> std::io::Result<()> {
+        writeln!(writer, "# {}", self.path)?;
+        if let Some(library) = material_library {
+            writeln!(writer, "mtllib {}", library)?;
+        }
+        for (i, vertex) in self.vertices.iter().enumerate() {
+            match self.colors.get(i) {
+                Some(color) => writeln!(writer, "v {} {} {} {} {} {}", vertex.x, vertex.y, vertex.z, color.x, color.y, color.z)?,
+                None => writeln!(writer, "v {} {} {}", vertex.x, vertex.y, vertex.z)?,
+            }
+        }
+        for uv in &self.uvs {
+            if uv.z == 0. {
+                writeln!(writer, "vt {} {}", uv.x, uv.y)?;
+            } else {
+                writeln!(writer, "vt {} {} {}", uv.x, uv.y, uv.z)?;
+            }
+        }
+        for normal in &self.normals {
+            writeln!(writer, "vn {} {} {}", normal.x, normal.y, normal.z)?;
+        }
+
+        if self.point_cloud {
+            return Ok(());
+        }
+
+        let mut groups = self.groups.iter().peekable();
+        let mut materials = self.materials.iter().peekable();
+        let mut material_set = false;
+        for (i, face) in self.faces.chunks_exact(3).enumerate() {
+            let start = (i * 3) as u32;
+            while let Some((_, name)) = groups.next_if(|(first, _)| *first <= start) {
+                writeln!(writer, "g {}", name)?;
+            }
+            while let Some((_, name)) = materials.next_if(|(first, _)| *first <= start) {
+                match name {
+                    Some(name) => writeln!(writer, "usemtl {}", name)?,
+                    // the previous material would carry on otherwise
+                    None if material_set => writeln!(writer, "usemtl {}", DEFAULT_MATERIAL)?,
+                    None => continue,
+                }
+                material_set = true;
+            }
+            let [a, b, c] = [face[0] + 1, face[1] + 1, face[2] + 1];
+            writeln!(writer, "f {a}/{a}/{a} {b}/{b}/{b} {c}/{c}/{c}")?;
+        }
+        Ok(())
+    }
+
+    /// Write the materials the mesh was loaded with as an ASCII .mtl library, sorted by name
+    ///
+    /// colors, shininess and opacity are kept, along with the paths of the texture maps as they were loaded,
+    /// relative to the working directory rather than to the library.
+    /// Maps embedded in their source file have no path to refer to and are left out
+    pub fn write_mtl<W: Write>(&self, writer: &mut W) -> std::io::Result<()> {
+        let mut library: Vec<_> = self.material_sources.iter().collect();
+        library.sort_by_key(|(name, _)| *name);
+        for (name, material) in library {
+            let data = &material.data;
+            writeln!(writer, "newmtl {}", name)?;
+            writeln!(writer, "Kd {} {} {}", data.color.x, data.color.y, data.color.z)?;
+            writeln!(writer, "Ks {} {} {}", data.specular.x, data.specular.y, data.specular.z)?;
+            writeln!(writer, "Ns {}", data.specular.w * 1000.)?;
+            writeln!(writer, "d {}", data.color.w)?;
+            for (slot, statement) in [(DIFFUSE_MAP, "map_Kd"), (NORMAL_MAP, "map_Bump"), (SPECULAR_MAP, "map_Ks")] {
+                match material.maps.get(slot) {
+                    Some(path) if path != default_map(slot) && !material.map_bytes.contains_key(&slot) => {
+                        writeln!(writer, "{} {}", statement, path)?
+                    }
+                    _ => {}
+                }
+            }
+            writeln!(writer)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,613 @@
+//! PLY parser
+//!
+//! Loads ASCII, binary little endian and binary big endian ply files.
+//! Vertices keep their normals, uvs and colors when the file provides them,
+//! files without faces are loaded as point clouds
+
+use glam::Vec4;
+
+use crate::error::{MeshParseError, MeshParseErrorKind};
+use crate::Vec3;
+
+use super::obj_parser::{column, triangulate, LineResult};
+use super::solver::{generate_normals, solve_indices};
+use super::{Mesh, Triangle};
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Format {
+    Ascii,
+    LittleEndian,
+    BigEndian,
+}
+
+#[derive(Clone, Copy)]
+enum Scalar {
+    I8,
+    U8,
+    I16,
+    U16,
+    I32,
+    U32,
+    F32,
+    F64,
+}
+
+impl Scalar {
+    fn from_name(name: &str) -> Option<Scalar> {
+        match name {
+            "char" | "int8" => Some(Scalar::I8),
+            "uchar" | "uint8" => Some(Scalar::U8),
+            "short" | "int16" => Some(Scalar::I16),
+            "ushort" | "uint16" => Some(Scalar::U16),
+            "int" | "int32" => Some(Scalar::I32),
+            "uint" | "uint32" => Some(Scalar::U32),
+            "float" | "float32" => Some(Scalar::F32),
+            "double" | "float64" => Some(Scalar::F64),
+            _ => None,
+        }
+    }
+
+    fn size(self) -> usize {
+        match self {
+            Scalar::I8 | Scalar::U8 => 1,
+            Scalar::I16 | Scalar::U16 => 2,
+            Scalar::I32 | Scalar::U32 | Scalar::F32 => 4,
+            Scalar::F64 => 8,
+        }
+    }
+
+    /// The largest value of integer types, used to bring colors to the 0..1 range
+    fn max(self) -> f64 {
+        match self {
+            Scalar::I8 => i8::MAX as f64,
+            Scalar::U8 => u8::MAX as f64,
+            Scalar::I16 => i16::MAX as f64,
+            Scalar::U16 => u16::MAX as f64,
+            Scalar::I32 => i32::MAX as f64,
+            Scalar::U32 => u32::MAX as f64,
+            Scalar::F32 | Scalar::F64 => 1.,
+        }
+    }
+
+    /// Read a binary value at the start of `bytes`, `None` when they are too short
+    fn read(self, bytes: &[u8], format: Format) -> Option<f64> {
+        let bytes = bytes.get(..self.size())?;
+        macro_rules! read {
+            ($type:ty) => {{
+                let array = bytes.try_into().ok()?;
+                match format {
+                    Format::BigEndian => <$type>::from_be_bytes(array) as f64,
+                    _ => <$type>::from_le_bytes(array) as f64,
+                }
+            }};
+        }
+        Some(match self {
+            Scalar::I8 => read!(i8),
+            Scalar::U8 => read!(u8),
+            Scalar::I16 => read!(i16),
+            Scalar::U16 => read!(u16),
+            Scalar::I32 => read!(i32),
+            Scalar::U32 => read!(u32),
+            Scalar::F32 => read!(f32),
+            Scalar::F64 => read!(f64),
+        })
+    }
+}
+
+enum Property {
+    Scalar(Scalar, String),
+    /// a list of values preceded by their count
+    List(Scalar, Scalar, String),
+}
+
+impl Property {
+    fn name(&self) -> &str {
+        match self {
+            Property::Scalar(_, name) | Property::List(_, _, name) => name,
+        }
+    }
+}
+
+struct Element {
+    name: String,
+    count: usize,
+    properties: Vec<Property>,
+}
+
+impl Element {
+    fn property(&self, names: &[&str]) -> Option<usize> {
+        self.properties.iter().position(|property| names.contains(&property.name()))
+    }
+}
+
+pub(crate) struct PLYMesh {
+    pub(crate) vertices: Vec<Vec3>,
+    pub(crate) normals: Vec<Vec3>,
+    pub(crate) uvs: Vec<Vec3>,
+    pub(crate) colors: Vec<Vec4>,
+    pub(crate) triangles: Vec<Triangle>,
+    pub(crate) warnings: Vec<MeshParseError>,
+    format: Format,
+    elements: Vec<Element>,
+}
+
+impl PLYMesh {
+    pub fn new() -> PLYMesh {
+        PLYMesh {
+            vertices: vec![],
+            normals: vec![],
+            uvs: vec![],
+            colors: vec![],
+            triangles: vec![],
+            warnings: vec![],
+            format: Format::Ascii,
+            elements: vec![],
+        }
+    }
+
+    pub fn load_ply(&mut self, file_name: &str) -> Result<(), MeshParseError> {
+        match std::fs::read(file_name) {
+            Ok(bytes) => self.load_ply_bytes(&bytes, file_name),
+            Err(why) => Err(MeshParseError::new(file_name, 0, 0, MeshParseErrorKind::Io(why))),
+        }
+    }
+
+    /// Parse ply data, `file_name` is used in errors
+    pub fn load_ply_bytes(&mut self, bytes: &[u8], file_name: &str) -> Result<(), MeshParseError> {
+        let mut offset = 0;
+        let mut line_idx = 0;
+        loop {
+            let Some(len) = bytes[offset..].iter().position(|&byte| byte == b'\n') else {
+                return Err(MeshParseError::new(file_name, line_idx + 1, 0, MeshParseErrorKind::MissingValue));
+            };
+            let line = String::from_utf8_lossy(&bytes[offset..offset + len]);
+            let line = line.trim_end_matches('\r');
+            offset += len + 1;
+            line_idx += 1;
+            let end = self.parse_header_line(line, line_idx).map_err(|(column, kind)| {
+                MeshParseError::new(file_name, line_idx, column, kind)
+            })?;
+            if end {
+                break;
+            }
+        }
+
+        let values = match self.format {
+            Format::Ascii => self.read_ascii(&bytes[offset..], line_idx, file_name)?,
+            format => self.read_binary(&bytes[offset..], format, file_name)?,
+        };
+        let elements = std::mem::take(&mut self.elements);
+        // other elements are read to get past them, but ignored
+        for (element, items) in elements.iter().zip(values) {
+            let result = match element.name.as_str() {
+                "vertex" => self.add_vertices(element, &items),
+                "face" => self.add_faces(element, &items).map(|skipped| {
+                    if skipped > 0 {
+                        let feature = format!("{} faces of less than 3 vertices", skipped);
+                        let kind = MeshParseErrorKind::UnsupportedFeature(feature);
+                        self.warnings.push(MeshParseError::new(file_name, 0, 0, kind));
+                    }
+                }),
+                _ => Ok(()),
+            };
+            result.map_err(|kind| MeshParseError::new(file_name, 0, 0, kind))?;
+        }
+        if self.triangles.iter().any(|triangle| triangle.normals.is_none()) {
+            generate_normals(&self.vertices, &[], &mut self.normals, &mut self.triangles);
+        }
+        Ok(())
+    }
+
+    /// Parse a line of the header, telling whether it was the last one
+    fn parse_header_line(&mut self, line: &str, line_idx: usize) -> LineResult<bool> {
+        let mut tokens = line.split_ascii_whitespace();
+        let keyword = tokens.next().unwrap_or("");
+        if line_idx == 1 {
+            return match keyword {
+                "ply" => Ok(false),
+                _ => Err((column(line, keyword), MeshParseErrorKind::UnsupportedFormat)),
+            };
+        }
+        let mut next = || tokens.next().ok_or((line.len() + 1, MeshParseErrorKind::MissingValue));
+        match keyword {
+            "" | "comment" | "obj_info" => {}
+            "format" => {
+                let format = next()?;
+                self.format = match format {
+                    "ascii" => Format::Ascii,
+                    "binary_little_endian" => Format::LittleEndian,
+                    "binary_big_endian" => Format::BigEndian,
+                    _ => return Err((column(line, format), MeshParseErrorKind::UnsupportedFormat)),
+                };
+            }
+            "element" => {
+                let name = next()?;
+                let count = next()?;
+                self.elements.push(Element {
+                    name: name.into(),
+                    count: count.parse().map_err(|_| (column(line, count), MeshParseErrorKind::InvalidNumber))?,
+                    properties: vec![],
+                });
+            }
+            "property" => {
+                let scalar = |name: &str| {
+                    Scalar::from_name(name).ok_or((column(line, name), MeshParseErrorKind::UnsupportedStatement))
+                };
+                let kind = next()?;
+                let property = match kind {
+                    "list" => {
+                        let count = scalar(next()?)?;
+                        let item = scalar(next()?)?;
+                        Property::List(count, item, next()?.into())
+                    }
+                    _ => Property::Scalar(scalar(kind)?, next()?.into()),
+                };
+                match self.elements.last_mut() {
+                    Some(element) => element.properties.push(property),
+                    None => return Err((column(line, keyword), MeshParseErrorKind::MissingValue)),
+                }
+            }
+            "end_header" => return Ok(true),
+            _ => return Err((column(line, keyword), MeshParseErrorKind::UnsupportedStatement)),
+        }
+        Ok(false)
+    }
+
+    /// values of every property of every item of each element, one line per item
+    fn read_ascii(&self, bytes: &[u8], header_lines: usize, file_name: &str) -> Result<Vec<Vec<Vec<f64>>>, MeshParseError> {
+        let text = String::from_utf8_lossy(bytes);
+        let mut lines = text.lines().enumerate().filter(|(_, line)| !line.trim().is_empty());
+        let mut values = vec![];
+        for element in &self.elements {
+            // each item takes at least a byte, so that corrupted counts don't allocate
+            let mut items = Vec::with_capacity(element.count.min(bytes.len()));
+            for _ in 0..element.count {
+                let Some((line_idx, line)) = lines.next() else {
+                    return Err(MeshParseError::new(file_name, 0, 0, MeshParseErrorKind::MissingValue));
+                };
+                let item = Self::parse_ascii_item(element, line).map_err(|(column, kind)| {
+                    MeshParseError::new(file_name, header_lines + line_idx + 1, column, kind)
+                })?;
+                items.push(item);
+            }
+            values.push(items);
+        }
+        Ok(values)
+    }
+
+    fn parse_ascii_item(element: &Element, line: &str) -> LineResult<Vec<f64>> {
+        let mut tokens = line.split_ascii_whitespace();
+        let mut next = || {
+            let token = tokens.next().ok_or((line.len() + 1, MeshParseErrorKind::MissingValue))?;
+            token.parse::<f64>().map_err(|_| (column(line, token), MeshParseErrorKind::InvalidNumber))
+        };
+        let mut item = vec![];
+        for property in &element.properties {
+            match property {
+                Property::Scalar(..) => item.push(next()?),
+                Property::List(..) => {
+                    let count = next()? as usize;
+                    item.push(count as f64);
+                    for _ in 0..count {
+                        item.push(next()?);
+                    }
+                }
+            }
+        }
+        Ok(item)
+    }
+
+    /// values of every property of every item of each element, see [`Self::read_ascii()`]
+    fn read_binary(&self, bytes: &[u8], format: Format, file_name: &str) -> Result<Vec<Vec<Vec<f64>>>, MeshParseError> {
+        let truncated = || MeshParseError::new(file_name, 0, 0, MeshParseErrorKind::MissingValue);
+        let mut offset = 0;
+        let mut read = |scalar: Scalar| {
+            let value = scalar.read(&bytes[offset.min(bytes.len())..], format).ok_or_else(truncated)?;
+            offset += scalar.size();
+            Ok::<f64, MeshParseError>(value)
+        };
+        let mut values = vec![];
+        for element in &self.elements {
+            let mut items = Vec::with_capacity(element.count.min(bytes.len()));
+            for _ in 0..element.count {
+                let mut item = vec![];
+                for property in &element.properties {
+                    match property {
+                        Property::Scalar(scalar, _) => item.push(read(*scalar)?),
+                        Property::List(count, scalar, _) => {
+                            let count = read(*count)? as usize;
+                            item.push(count as f64);
+                            for _ in 0..count {
+                                item.push(read(*scalar)?);
+                            }
+                        }
+                    }
+                }
+                items.push(item);
+            }
+            values.push(items);
+        }
+        Ok(values)
+    }
+
+    /// The index of the first value of a property in an item, lists coming before it being skipped
+    fn value_offset(element: &Element, item: &[f64], property: usize) -> usize {
+        let mut offset = 0;
+        for previous in &element.properties[..property] {
+            offset += match previous {
+                Property::Scalar(..) => 1,
+                Property::List(..) => 1 + item[offset] as usize,
+            };
+        }
+        offset
+    }
+
+    fn add_vertices(&mut self, element: &Element, items: &[Vec<f64>]) -> Result<(), MeshParseErrorKind> {
+        let column_of = |names: &[&str]| {
+            element.property(names).and_then(|property| match &element.properties[property] {
+                Property::Scalar(scalar, _) => Some((
+                    *scalar,
+                    items.iter().map(|item| item[Self::value_offset(element, item, property)]).collect::<Vec<f64>>(),
+                )),
+                Property::List(..) => None,
+            })
+        };
+        let vector = |names: [&[&str]; 3]| -> Option<Vec<Vec3>> {
+            let [x, y, z] = names.map(column_of);
+            let (x, y) = (x?.1, y?.1);
+            let z = z.map(|(_, z)| z);
+            Some(
+                (0..items.len())
+                    .map(|i| Vec3::new(x[i] as f32, y[i] as f32, z.as_ref().map_or(0., |z| z[i] as f32)))
+                    .collect(),
+            )
+        };
+
+        self.vertices = match vector([&["x"], &["y"], &["z"]]) {
+            Some(vertices) => vertices,
+            None => return Err(MeshParseErrorKind::MissingValue),
+        };
+        self.normals = vector([&["nx"], &["ny"], &["nz"]]).unwrap_or_default();
+        self.uvs = vector([&["u", "s", "texture_u", "texture_s"], &["v", "t", "texture_v", "texture_t"], &[]])
+            .unwrap_or_default();
+
+        let channels = [
+            column_of(&["red", "r", "diffuse_red"]),
+            column_of(&["green", "g", "diffuse_green"]),
+            column_of(&["blue", "b", "diffuse_blue"]),
+            column_of(&["alpha", "a", "diffuse_alpha"]),
+        ];
+        if channels[..3].iter().all(Option::is_some) {
+            let channel = |channel: &Option<(Scalar, Vec<f64>)>, i: usize| {
+                channel.as_ref().map_or(1., |(scalar, values)| (values[i] / scalar.max()) as f32)
+            };
+            self.colors = (0..items.len())
+                .map(|i| Vec4::new(
+                    channel(&channels[0], i),
+                    channel(&channels[1], i),
+                    channel(&channels[2], i),
+                    channel(&channels[3], i),
+                ))
+                .collect();
+        }
+        Ok(())
+    }
+
+    /// Triangulate every face, giving the number of those skipped for having less than 3 vertices
+    fn add_faces(&mut self, element: &Element, items: &[Vec<f64>]) -> Result<usize, MeshParseErrorKind> {
+        let Some(property) = element.property(&["vertex_indices", "vertex_index"]) else {
+            return Err(MeshParseErrorKind::MissingValue);
+        };
+        let mut skipped = 0;
+        for item in items {
+            let offset = Self::value_offset(element, item, property);
+            let count = item[offset] as usize;
+            let values = &item[offset + 1..offset + 1 + count];
+            // casting would turn negative and fractional indices into valid ones
+            if values
+                .iter()
+                .any(|&index| index < 0. || index.fract() != 0. || index >= self.vertices.len() as f64)
+            {
+                return Err(MeshParseErrorKind::IndexOutOfRange);
+            }
+            if count < 3 {
+                skipped += 1;
+                continue;
+            }
+            let indices: Vec<usize> = values.iter().map(|&index| index as usize).collect();
+            let polygon: Vec<Vec3> = indices.iter().map(|&index| self.vertices[index]).collect();
+            for [a, b, c] in triangulate(&polygon) {
+                let points = [indices[a], indices[b], indices[c]];
+                let mut triangle = Triangle::new();
+                triangle.points = points;
+                triangle.textures = (!self.uvs.is_empty()).then_some(points);
+                if self.normals.is_empty() {
+                    // faces without normals share smooth ones
+                    triangle.smoothing = Some(1);
+                    triangle.normals = None;
+                } else {
+                    triangle.normals = Some(points);
+                }
+                self.triangles.push(triangle);
+            }
+        }
+        Ok(skipped)
+    }
+}
+
+impl Mesh {
+    /// Load a mesh from an ASCII or binary .ply file
+    ///
+    /// Vertex colors are kept, see [`Self::colors()`].
+    /// A file without faces is loaded as a point cloud, see [`Self::is_point_cloud()`].
+    /// Faces of less than 3 vertices are skipped and counted in [`Self::warnings()`],
+    /// a file holding only those becoming a point cloud as well
+    pub fn from_ply(file_name: &str) -> Result<Mesh, MeshParseError> {
+        let mut ply = PLYMesh::new();
+        ply.load_ply(file_name)?;
+        Ok(Self::from_ply_mesh(ply, file_name))
+    }
+
+    /// Load a mesh from ASCII or binary ply data in memory, see [`Self::from_ply()`]
+    pub fn from_ply_bytes(bytes: &[u8], name: &str) -> Result<Mesh, MeshParseError> {
+        let mut ply = PLYMesh::new();
+        ply.load_ply_bytes(bytes, name)?;
+        Ok(Self::from_ply_mesh(ply, name))
+    }
+
+    fn from_ply_mesh(ply: PLYMesh, path: &str) -> Mesh {
+        let mut mesh = Mesh::new();
+        mesh.path = path.into();
+        mesh.warnings = ply.warnings;
+        if ply.triangles.is_empty() {
+            // every point is drawn once, missing attributes being left to zero
+            let count = ply.vertices.len();
+            mesh.faces = (0..count as u32).collect();
+            mesh.normals = ply.normals;
+            mesh.normals.resize(count, Vec3::ZERO);
+            mesh.uvs = ply.uvs;
+            mesh.uvs.resize(count, Vec3::ZERO);
+            mesh.vertices = ply.vertices;
+            mesh.colors = ply.colors;
+            mesh.point_cloud = true;
+            mesh.update_bounds();
+            return mesh;
+        }
+        let (vertices, uvs, normals, faces, sources) =
+            solve_indices(&ply.vertices, &ply.uvs, &ply.normals, &ply.triangles, None);
+        if !ply.colors.is_empty() {
+            mesh.colors = sources.iter().map(|&src| ply.colors[src]).collect();
+        }
+        mesh.faces = faces.iter().map(|x| *x as u32).collect();
+        mesh.vertices = vertices;
+        mesh.uvs = uvs;
+        mesh.normals = normals;
+        mesh.update_bounds();
+        mesh
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HEADER: &str = "element vertex 4
+property float x
+property float y
+property float z
+property uchar red
+property uchar green
+property uchar blue
+element face 1
+property list uchar int vertex_indices
+end_header
+";
+
+    const POSITIONS: [Vec3; 4] = [Vec3::ZERO, Vec3::X, Vec3::new(1., 1., 0.), Vec3::Y];
+
+    fn load(bytes: &[u8]) -> Mesh {
+        Mesh::from_ply_bytes(bytes, "test.ply").unwrap()
+    }
+
+    fn load_error(bytes: &[u8]) -> MeshParseError {
+        match Mesh::from_ply_bytes(bytes, "test.ply") {
+            Ok(_) => panic!("invalid ply data loaded"),
+            Err(why) => why,
+        }
+    }
+
+    fn ascii() -> String {
+        let mut ply = format!("ply\nformat ascii 1.0\ncomment a red quad\n{HEADER}");
+        for position in POSITIONS {
+            ply += &format!("{} {} {} 255 0 0\n", position.x, position.y, position.z);
+        }
+        ply + "4 0 1 2 3\n"
+    }
+
+    fn binary(format: Format) -> Vec<u8> {
+        let name = match format {
+            Format::BigEndian => "binary_big_endian",
+            _ => "binary_little_endian",
+        };
+        let ordered = |mut bytes: [u8; 4]| {
+            if format == Format::BigEndian {
+                bytes.reverse();
+            }
+            bytes
+        };
+        let mut ply = format!("ply\nformat {name} 1.0\n{HEADER}").into_bytes();
+        for position in POSITIONS {
+            ply.extend(position.to_array().iter().flat_map(|value| ordered(value.to_le_bytes())));
+            ply.extend([255, 0, 0]);
+        }
+        ply.push(4);
+        ply.extend([0i32, 1, 2, 3].iter().flat_map(|index| ordered(index.to_le_bytes())));
+        ply
+    }
+
+    #[test]
+    fn ascii_files_load() {
+        let mesh = load(ascii().as_bytes());
+        assert_eq!(mesh.faces.len(), 6);
+        assert_eq!(mesh.vertices.len(), 4);
+        assert!(!mesh.is_point_cloud());
+        assert!(mesh.colors.iter().all(|color| *color == Vec4::new(1., 0., 0., 1.)));
+        assert!(mesh.normals.iter().all(|normal| normal.abs_diff_eq(Vec3::Z, 1e-5)));
+    }
+
+    #[test]
+    fn binary_files_load_like_ascii_ones() {
+        let ascii = load(ascii().as_bytes());
+        for format in [Format::LittleEndian, Format::BigEndian] {
+            let binary = load(&binary(format));
+            assert_eq!(binary.faces, ascii.faces);
+            assert_eq!(binary.vertices, ascii.vertices);
+            assert_eq!(binary.colors, ascii.colors);
+        }
+
+        let mut truncated = binary(Format::BigEndian);
+        truncated.pop();
+        assert!(matches!(load_error(&truncated).kind, MeshParseErrorKind::MissingValue));
+    }
+
+    #[test]
+    fn faceless_files_are_point_clouds() {
+        let mesh = load(b"ply\nformat ascii 1.0\nelement vertex 2\nproperty float x\nproperty float y\nend_header\n1 2\n3 4\n");
+        assert!(mesh.is_point_cloud());
+        assert_eq!(mesh.faces, [0, 1]);
+        assert_eq!(mesh.vertices, [Vec3::new(1., 2., 0.), Vec3::new(3., 4., 0.)]);
+    }
+
+    #[test]
+    fn errors_are_located() {
+        let error = load_error(b"ply\nformat ascii 1.0\nelement vertex 1\nproperty float x\nproperty float y\nend_header\n1 y\n");
+        assert_eq!((error.line, error.column), (7, 3));
+        assert!(matches!(error.kind, MeshParseErrorKind::InvalidNumber));
+
+        let error = load_error(b"ply\nformat ascii 1.0\nproperty float x\n");
+        assert_eq!((error.line, error.column), (3, 1));
+
+        // counts larger than the data fail without reserving room for them
+        let error = load_error(b"ply\nformat ascii 1.0\nelement vertex 4000000000000\nproperty float x\nend_header\n1\n");
+        assert!(matches!(error.kind, MeshParseErrorKind::MissingValue));
+    }
+
+    #[test]
+    fn indices_must_be_whole_and_positive() {
+        let triangle = "ply\nformat ascii 1.0\nelement vertex 3\nproperty float x\nproperty float y\nelement face 1\nproperty list uchar float vertex_indices\nend_header\n0 0\n1 0\n0 1\n";
+        for face in ["3 0 1 -1", "3 0 1 1.5", "3 0 1 3"] {
+            let error = load_error(format!("{triangle}{face}\n").as_bytes());
+            assert!(matches!(error.kind, MeshParseErrorKind::IndexOutOfRange), "{face}");
+        }
+        assert_eq!(load(format!("{triangle}3 0 1 2\n").as_bytes()).faces.len(), 3);
+    }
+
+    #[test]
+    fn degenerate_faces_are_warnings() {
+        let ply = "ply\nformat ascii 1.0\nelement vertex 2\nproperty float x\nproperty float y\nelement face 2\nproperty list uchar int vertex_indices\nend_header\n0 0\n1 0\n2 0 1\n1 1\n";
+        let mesh = load(ply.as_bytes());
+        assert!(mesh.is_point_cloud());
+        assert_eq!(mesh.warnings().len(), 1);
+        assert!(matches!(&mesh.warnings()[0].kind, MeshParseErrorKind::UnsupportedFeature(feature) if feature.starts_with("2 faces")));
+    }
+}
@@ -0,0 +1,368 @@
+//! Primitive shapes
+//!
+//! Parametric generators of common shapes, with smooth or flat normals where they belong and uvs in the 0..1 range.
+//! Shapes are centered on the origin and their axis is `Z`, the up axis of the camera.
+//! Resolutions below the minimum of a shape are raised to it
+
+use std::f32::consts::{FRAC_PI_2, PI, TAU};
+
+use glam::Vec2;
+
+use crate::Vec3;
+
+use super::{Mesh, MeshBuilder};
+
+/// A primitive shape with its parameters, see [`Primitive::mesh()`]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Primitive {
+    Cube { size: Vec3 },
+    UvSphere { radius: f32, segments: u32, rings: u32 },
+    Icosphere { radius: f32, subdivisions: u32 },
+    Plane { size: Vec2, subdivisions: u32 },
+    Cylinder { radius: f32, height: f32, segments: u32 },
+    Cone { radius: f32, height: f32, segments: u32 },
+    Torus { major_radius: f32, minor_radius: f32, segments: u32, sides: u32 },
+    Capsule { radius: f32, height: f32, segments: u32, rings: u32 },
+}
+
+impl Primitive {
+    /// Generate the mesh of the shape
+    pub fn mesh(&self) -> Mesh {
+        match *self {
+            Primitive::Cube { size } => cube(size),
+            Primitive::UvSphere { radius, segments, rings } => uv_sphere(radius, segments, rings),
+            Primitive::Icosphere { radius, subdivisions } => icosphere(radius, subdivisions),
+            Primitive::Plane { size, subdivisions } => plane(size, subdivisions),
+            Primitive::Cylinder { radius, height, segments } => cylinder(radius, height, segments),
+            Primitive::Cone { radius, height, segments } => cone(radius, height, segments),
+            Primitive::Torus { major_radius, minor_radius, segments, sides } => {
+                torus(major_radius, minor_radius, segments, sides)
+            }
+            Primitive::Capsule { radius, height, segments, rings } => capsule(radius, height, segments, rings),
+        }
+    }
+}
+
+/// The builder only fails on faces referring to missing vertices, which generators never add
+fn build(builder: MeshBuilder) -> Mesh {
+    builder.build().unwrap_or_else(|_| Mesh::new())
+}
+
+/// A box of the given size, each side having its own flat normal and the whole uv square
+pub fn cube(size: Vec3) -> Mesh {
+    let half = size * 0.5;
+    // normal of each side, with two axes along it whose cross product is the normal
+    let sides = [
+        (Vec3::X, Vec3::Y, Vec3::Z),
+        (Vec3::NEG_X, Vec3::NEG_Y, Vec3::Z),
+        (Vec3::Y, Vec3::NEG_X, Vec3::Z),
+        (Vec3::NEG_Y, Vec3::X, Vec3::Z),
+        (Vec3::Z, Vec3::X, Vec3::Y),
+        (Vec3::NEG_Z, Vec3::NEG_X, Vec3::Y),
+    ];
+    let mut builder = MeshBuilder::new();
+    for (normal, u, v) in sides {
+        let corners = [(0., 0.), (1., 0.), (1., 1.), (0., 1.)].map(|(s, t): (f32, f32)| {
+            let position = (normal + u * (s * 2. - 1.) + v * (t * 2. - 1.)) * half;
+            builder.add_vertex(position, Vec3::new(s, t, 0.), normal)
+        });
+        builder.add_quad(corners[0], corners[1], corners[2], corners[3]);
+    }
+    build(builder)
+}
+
+/// A surface of revolution around `Z`, from rows of (polar angle from `-Z`, height offset, v coordinate)
+///
+/// rows at a pole collapse to a point, their degenerate triangles are left out
+fn lathe(radius: f32, segments: u32, rows: &[(f32, f32, f32)]) -> Mesh {
+    let mut builder = MeshBuilder::new();
+    for &(theta, offset, v) in rows {
+        for s in 0..=segments {
+            let phi = TAU * s as f32 / segments as f32;
+            let normal = Vec3::new(theta.sin() * phi.cos(), theta.sin() * phi.sin(), -theta.cos());
+            let position = normal * radius + Vec3::new(0., 0., offset);
+            builder.add_vertex(position, Vec3::new(s as f32 / segments as f32, v, 0.), normal);
+        }
+    }
+    let columns = segments + 1;
+    for (r, pair) in rows.windows(2).enumerate() {
+        for s in 0..segments {
+            let a = r as u32 * columns + s;
+            let (b, c, d) = (a + 1, a + columns + 1, a + columns);
+            if pair[0].0 > 0. {
+                builder.add_triangle(a, b, c);
+            }
+            if pair[1].0 < PI {
+                builder.add_triangle(a, c, d);
+            }
+        }
+    }
+    build(builder)
+}
+
+/// A sphere made of `segments` meridians and `rings` parallels, uvs following longitude and latitude
+pub fn uv_sphere(radius: f32, segments: u32, rings: u32) -> Mesh {
+    let (segments, rings) = (segments.max(3), rings.max(2));
+    let rows: Vec<(f32, f32, f32)> = (0..=rings)
+        .map(|r| {
+            let v = r as f32 / rings as f32;
+            (PI * v, 0., v)
+        })
+        .collect();
+    lathe(radius, segments, &rows)
+}
+
+/// A sphere made of evenly sized triangles, from an icosahedron whose faces are split in four `subdivisions` times
+///
+/// uvs are mapped from longitude and latitude, vertices on the seam being split and faces crossing it reaching past 1
+pub fn icosphere(radius: f32, subdivisions: u32) -> Mesh {
+    let t = (1. + 5f32.sqrt()) / 2.;
+    let mut points: Vec<Vec3> = [
+        (-1., t, 0.), (1., t, 0.), (-1., -t, 0.), (1., -t, 0.),
+        (0., -1., t), (0., 1., t), (0., -1., -t), (0., 1., -t),
+        (t, 0., -1.), (t, 0., 1.), (-t, 0., -1.), (-t, 0., 1.),
+    ]
+    .iter()
+    .map(|&(x, y, z)| Vec3::new(x, y, z).normalize())
+    .collect();
+    let mut faces: Vec<[usize; 3]> = vec![
+        [0, 11, 5], [0, 5, 1], [0, 1, 7], [0, 7, 10], [0, 10, 11],
+        [1, 5, 9], [5, 11, 4], [11, 10, 2], [10, 7, 6], [7, 1, 8],
+        [3, 9, 4], [3, 4, 2], [3, 2, 6], [3, 6, 8], [3, 8, 9],
+        [4, 9, 5], [2, 4, 11], [6, 2, 10], [8, 6, 7], [9, 8, 1],
+    ];
+    for _ in 0..subdivisions {
+        let mut midpoints = std::collections::HashMap::new();
+        let mut midpoint = |a: usize, b: usize| {
+            *midpoints.entry((a.min(b), a.max(b))).or_insert_with(|| {
+                points.push((points[a] + points[b]).normalize());
+                points.len() - 1
+            })
+        };
+        faces = faces
+            .iter()
+            .flat_map(|&[a, b, c]| {
+                let (ab, bc, ca) = (midpoint(a, b), midpoint(b, c), midpoint(c, a));
+                [[a, ab, ca], [b, bc, ab], [c, ca, bc], [ab, bc, ca]]
+            })
+            .collect();
+    }
+
+    let mut builder = MeshBuilder::new();
+    for face in faces {
+        let mut uvs = face.map(|point| {
+            let normal = points[point];
+            Vec3::new(normal.y.atan2(normal.x) / TAU + 0.5, (-normal.z).clamp(-1., 1.).acos() / PI, 0.)
+        });
+        // a face crossing the seam wraps around, its corners on the far side move past 1
+        let poles = face.map(|point| points[point].truncate().length() < 1e-6);
+        let max = (0..3).filter(|&i| !poles[i]).map(|i| uvs[i].x).fold(0., f32::max);
+        for uv in &mut uvs {
+            if max - uv.x > 0.5 {
+                uv.x += 1.;
+            }
+        }
+        // a pole has no longitude, it takes the one of the face
+        let others: Vec<f32> = (0..3).filter(|&i| !poles[i]).map(|i| uvs[i].x).collect();
+        for i in (0..3).filter(|&i| poles[i]) {
+            uvs[i].x = others.iter().sum::<f32>() / others.len() as f32;
+        }
+        let corners = [0, 1, 2].map(|i| builder.add_vertex(points[face[i]] * radius, uvs[i], points[face[i]]));
+        builder.add_triangle(corners[0], corners[1], corners[2]);
+    }
+    let mut mesh = build(builder);
+    mesh.weld_within(0.);
+    mesh
+}
+
+/// A flat rectangle facing `Z`, split in a grid of `subdivisions` + 1 squares along each side
+pub fn plane(size: Vec2, subdivisions: u32) -> Mesh {
+    let cells = subdivisions + 1;
+    let mut builder = MeshBuilder::new();
+    for j in 0..=cells {
+        for i in 0..=cells {
+            let uv = Vec2::new(i as f32, j as f32) / cells as f32;
+            let position = ((uv - 0.5) * size).extend(0.);
+            builder.add_vertex(position, uv.extend(0.), Vec3::Z);
+        }
+    }
+    for j in 0..cells {
+        for i in 0..cells {
+            let a = j * (cells + 1) + i;
+            builder.add_quad(a, a + 1, a + cells + 2, a + cells + 1);
+        }
+    }
+    build(builder)
+}
+
+/// Add a flat disc at `height`, facing up or down
+///
+/// its uvs are mirrored along `X` when facing down, so that textures read the same from outside
+fn disc(builder: &mut MeshBuilder, radius: f32, height: f32, segments: u32, up: bool) {
+    let normal = if up { Vec3::Z } else { Vec3::NEG_Z };
+    let mirror = if up { Vec2::ONE } else { Vec2::new(-1., 1.) };
+    let center = builder.add_vertex(Vec3::new(0., 0., height), Vec3::new(0.5, 0.5, 0.), normal);
+    for s in 0..segments {
+        let corners = [s, s + 1].map(|s| {
+            let phi = TAU * s as f32 / segments as f32;
+            let direction = Vec2::new(phi.cos(), phi.sin());
+            builder.add_vertex((direction * radius).extend(height), (direction * mirror * 0.5 + 0.5).extend(0.), normal)
+        });
+        if up {
+            builder.add_triangle(center, corners[0], corners[1]);
+        } else {
+            builder.add_triangle(center, corners[1], corners[0]);
+        }
+    }
+}
+
+/// A closed cylinder standing along `Z`, its side wrapped by the uvs and its caps flat shaded
+pub fn cylinder(radius: f32, height: f32, segments: u32) -> Mesh {
+    let segments = segments.max(3);
+    let half = height * 0.5;
+    let mut builder = MeshBuilder::new();
+    for s in 0..=segments {
+        let phi = TAU * s as f32 / segments as f32;
+        let normal = Vec3::new(phi.cos(), phi.sin(), 0.);
+        let u = s as f32 / segments as f32;
+        builder.add_vertex(normal * radius - Vec3::new(0., 0., half), Vec3::new(u, 0., 0.), normal);
+        builder.add_vertex(normal * radius + Vec3::new(0., 0., half), Vec3::new(u, 1., 0.), normal);
+    }
+    for s in 0..segments {
+        let a = s * 2;
+        builder.add_quad(a, a + 2, a + 3, a + 1);
+    }
+    disc(&mut builder, radius, -half, segments, false);
+    disc(&mut builder, radius, half, segments, true);
+    build(builder)
+}
+
+/// A closed cone standing along `Z`, its base at the bottom and its apex at the top
+pub fn cone(radius: f32, height: f32, segments: u32) -> Mesh {
+    let segments = segments.max(3);
+    let half = height * 0.5;
+    let mut builder = MeshBuilder::new();
+    let slope = |phi: f32| Vec3::new(phi.cos() * height, phi.sin() * height, radius).normalize_or_zero();
+    for s in 0..=segments {
+        let phi = TAU * s as f32 / segments as f32;
+        let position = Vec3::new(phi.cos() * radius, phi.sin() * radius, -half);
+        builder.add_vertex(position, Vec3::new(s as f32 / segments as f32, 0., 0.), slope(phi));
+    }
+    // the apex is split between segments, each having the normal of its middle
+    for s in 0..segments {
+        let phi = TAU * (s as f32 + 0.5) / segments as f32;
+        let apex = Vec3::new(0., 0., half);
+        let apex = builder.add_vertex(apex, Vec3::new((s as f32 + 0.5) / segments as f32, 1., 0.), slope(phi));
+        builder.add_triangle(s, s + 1, apex);
+    }
+    disc(&mut builder, radius, -half, segments, false);
+    build(builder)
+}
+
+/// A ring around `Z`, its tube of `minor_radius` following a circle of `major_radius`
+///
+/// `segments` split the ring along its circle and `sides` split the tube around it
+pub fn torus(major_radius: f32, minor_radius: f32, segments: u32, sides: u32) -> Mesh {
+    let (segments, sides) = (segments.max(3), sides.max(3));
+    let mut builder = MeshBuilder::new();
+    for i in 0..=segments {
+        let phi = TAU * i as f32 / segments as f32;
+        let direction = Vec3::new(phi.cos(), phi.sin(), 0.);
+        for j in 0..=sides {
+            let theta = TAU * j as f32 / sides as f32;
+            let normal = direction * theta.cos() + Vec3::Z * theta.sin();
+            let uv = Vec3::new(i as f32 / segments as f32, j as f32 / sides as f32, 0.);
+            builder.add_vertex(direction * major_radius + normal * minor_radius, uv, normal);
+        }
+    }
+    for i in 0..segments {
+        for j in 0..sides {
+            let a = i * (sides + 1) + j;
+            let b = a + sides + 1;
+            builder.add_quad(a, b, b + 1, a + 1);
+        }
+    }
+    build(builder)
+}
+
+/// A cylinder along `Z` capped by hemispheres, `height` being its total height
+///
+/// `rings` split each hemisphere, the cylinder part disappears when `height` is below twice the radius
+pub fn capsule(radius: f32, height: f32, segments: u32, rings: u32) -> Mesh {
+    let (segments, rings) = (segments.max(3), rings.max(1));
+    let height = height.max(radius * 2.);
+    let half = height * 0.5 - radius;
+    let v = |angle: f32, offset: f32| (offset - angle.cos() * radius) / height + 0.5;
+    let mut rows = vec![];
+    for (start, offset) in [(0., -half), (FRAC_PI_2, half)] {
+        for r in 0..=rings {
+            let theta = start + FRAC_PI_2 * r as f32 / rings as f32;
+            rows.push((theta, offset, v(theta, offset)));
+        }
+    }
+    // without a cylinder part both hemispheres end on the same row, which would form a flat band
+    rows.dedup();
+    lathe(radius, segments, &rows)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Check that the faces of a closed shape are valid, and that its normals point away from `inside`
+    fn assert_outward(mesh: &Mesh, inside: impl Fn(Vec3) -> Vec3) {
+        assert!(!mesh.faces.is_empty() && mesh.faces.len().is_multiple_of(3));
+        assert!(mesh.faces.iter().all(|&index| (index as usize) < mesh.vertices.len()));
+        for (position, normal) in mesh.vertices.iter().zip(&mesh.normals) {
+            assert!((normal.length() - 1.).abs() < 1e-5, "{normal} isn't unit length");
+            assert!(normal.dot(*position - inside(*position)) > 0., "{normal} points inward at {position}");
+        }
+        for face in mesh.faces.chunks_exact(3) {
+            let [a, b, c] = [face[0], face[1], face[2]].map(|index| mesh.vertices[index as usize]);
+            let face_normal = (b - a).cross(c - a);
+            assert!(face_normal.length() > 1e-6, "degenerate face {a} {b} {c}");
+            for &index in face {
+                assert!(face_normal.dot(mesh.normals[index as usize]) > 0., "face {a} {b} {c} is inside out");
+            }
+        }
+    }
+
+    #[test]
+    fn shapes_face_outward() {
+        let shapes = [
+            Primitive::Cube { size: Vec3::new(1., 2., 3.) },
+            Primitive::UvSphere { radius: 2., segments: 16, rings: 8 },
+            Primitive::Icosphere { radius: 1., subdivisions: 2 },
+            Primitive::Cylinder { radius: 1., height: 2., segments: 12 },
+            Primitive::Cone { radius: 1., height: 2., segments: 12 },
+            Primitive::Capsule { radius: 1., height: 4., segments: 12, rings: 4 },
+        ];
+        for shape in shapes {
+            assert_outward(&shape.mesh(), |_| Vec3::ZERO);
+        }
+        assert_outward(&plane(Vec2::ONE, 2), |position| position - Vec3::Z);
+    }
+
+    #[test]
+    fn tori_face_away_from_their_circle() {
+        let mesh = torus(2., 0.5, 16, 8);
+        assert_outward(&mesh, |position| position.truncate().extend(0.).normalize() * 2.);
+    }
+
+    #[test]
+    fn resolutions_are_raised_to_their_minimum() {
+        let shapes = [
+            (uv_sphere(1., 0, 0), uv_sphere(1., 3, 2)),
+            (cylinder(1., 1., 0), cylinder(1., 1., 3)),
+            (cone(1., 1., 1), cone(1., 1., 3)),
+            (capsule(1., 1., 2, 0), capsule(1., 1., 3, 1)),
+        ];
+        for (mesh, minimum) in shapes {
+            assert_eq!(mesh.faces, minimum.faces);
+            assert_outward(&mesh, |_| Vec3::ZERO);
+        }
+        let mesh = torus(2., 0.5, 0, 0);
+        assert_eq!(mesh.faces, torus(2., 0.5, 3, 3).faces);
+        assert_outward(&mesh, |position| position.truncate().extend(0.).normalize() * 2.);
+    }
+}
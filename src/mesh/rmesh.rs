@@ -0,0 +1,567 @@
+//! Binary mesh cache
+//!
+//! `.rmesh` files store the welded buffers of a [`Mesh`] along with its groups, materials and skeleton,
+//! so that they load without any parsing or welding.
+//!
+//! Layout, in little endian:
+//!     - a header: `RMSH`, the format version, a stamp of the source files and a crc32 of those
+//!     - chunks: a 4 bytes tag, the payload length, a crc32 of the payload and the payload itself
+//!
+//! Unknown chunks are skipped, so that newer files stay readable as long as the version matches
+
+use std::io::Write;
+use std::path::Path;
+use std::time::UNIX_EPOCH;
+
+use glam::{Mat4, Vec4};
+
+use crate::error::{MeshParseError, MeshParseErrorKind};
+use crate::material::{MaterialData, MaterialDescriptor};
+use crate::Vec3;
+
+use super::mtl_parser::MaterialLibrary;
+use super::{Bone, Mesh};
+
+const MAGIC: &[u8; 4] = b"RMSH";
+/// bumped whenever the layout of a chunk changes, older files being rebuilt from their source
+const VERSION: u32 = 2;
+const HEADER_SIZE: usize = 20;
+
+/// crc32 (IEEE) of some bytes
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for byte in bytes {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            crc = (crc >> 1) ^ (0xEDB8_8320 & 0u32.wrapping_sub(crc & 1));
+        }
+    }
+    !crc
+}
+
+/// Modification time of a file in nanoseconds, 0 when it can't be read
+fn modified_time(path: &Path) -> u64 {
+    std::fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+        .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+        .map_or(0, |time| time.as_nanos() as u64)
+}
+
+/// Stamp of the files a mesh was loaded from: a FNV-1a hash of the modification times of the mesh file,
+/// of its dependencies and of the texture files of its materials
+///
+/// 0 when the mesh file can't be read
+fn source_stamp(mesh: &Mesh) -> u64 {
+    let modified = modified_time(Path::new(&mesh.path));
+    if modified == 0 {
+        return 0;
+    }
+    let mut textures: Vec<&String> = mesh
+        .material_sources
+        .values()
+        .flat_map(|material| {
+            material
+                .maps
+                .iter()
+                .enumerate()
+                .filter(|(slot, _)| !material.map_bytes.contains_key(slot))
+                .map(|(_, path)| path)
+        })
+        .collect();
+    textures.sort();
+    textures.dedup();
+    let times = mesh.dependencies.iter().chain(textures).map(|path| modified_time(Path::new(path)));
+    let mut hash = 0xCBF2_9CE4_8422_2325u64;
+    for time in std::iter::once(modified).chain(times) {
+        for byte in time.to_le_bytes() {
+            hash = (hash ^ byte as u64).wrapping_mul(0x0000_0100_0000_01B3);
+        }
+    }
+    hash
+}
+
+/// The cache of a mesh file, stored next to it
+fn cache_path(file_name: &str) -> String {
+    format!("{}.rmesh", file_name)
+}
+
+#[derive(Default)]
+struct ChunkWriter {
+    bytes: Vec<u8>,
+}
+
+impl ChunkWriter {
+    fn u32(&mut self, value: u32) {
+        self.bytes.extend(value.to_le_bytes());
+    }
+
+    fn f32s(&mut self, values: &[f32]) {
+        for value in values {
+            self.bytes.extend(value.to_le_bytes());
+        }
+    }
+
+    fn string(&mut self, value: &str) {
+        self.u32(value.len() as u32);
+        self.bytes.extend(value.as_bytes());
+    }
+
+    fn optional_string(&mut self, value: Option<&str>) {
+        match value {
+            Some(value) => {
+                self.bytes.push(1);
+                self.string(value);
+            }
+            None => self.bytes.push(0),
+        }
+    }
+
+    fn vec3s(&mut self, values: &[Vec3]) {
+        self.u32(values.len() as u32);
+        for value in values {
+            self.f32s(&value.to_array());
+        }
+    }
+
+    fn vec4s(&mut self, values: &[Vec4]) {
+        self.u32(values.len() as u32);
+        for value in values {
+            self.f32s(&value.to_array());
+        }
+    }
+}
+
+struct ChunkReader<'a> {
+    bytes: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> ChunkReader<'a> {
+    fn take(&mut self, len: usize) -> Option<&'a [u8]> {
+        let bytes = self.bytes.get(self.offset..self.offset.checked_add(len)?)?;
+        self.offset += len;
+        Some(bytes)
+    }
+
+    fn u8(&mut self) -> Option<u8> {
+        Some(self.take(1)?[0])
+    }
+
+    fn u32(&mut self) -> Option<u32> {
+        Some(u32::from_le_bytes(self.take(4)?.try_into().ok()?))
+    }
+
+    fn u64(&mut self) -> Option<u64> {
+        Some(u64::from_le_bytes(self.take(8)?.try_into().ok()?))
+    }
+
+    fn f32(&mut self) -> Option<f32> {
+        Some(f32::from_le_bytes(self.take(4)?.try_into().ok()?))
+    }
+
+    /// a count of items, checked against the remaining bytes so that corrupted counts don't allocate
+    fn count(&mut self, item_size: usize) -> Option<usize> {
+        let count = self.u32()? as usize;
+        (count.checked_mul(item_size)? <= self.bytes.len() - self.offset).then_some(count)
+    }
+
+    fn string(&mut self) -> Option<String> {
+        let len = self.count(1)?;
+        Some(String::from_utf8_lossy(self.take(len)?).into_owned())
+    }
+
+    fn optional_string(&mut self) -> Option<Option<String>> {
+        match self.u8()? {
+            0 => Some(None),
+            _ => Some(Some(self.string()?)),
+        }
+    }
+
+    fn vec4(&mut self) -> Option<Vec4> {
+        Some(Vec4::new(self.f32()?, self.f32()?, self.f32()?, self.f32()?))
+    }
+
+    fn vec3s(&mut self) -> Option<Vec<Vec3>> {
+        let count = self.count(12)?;
+        (0..count).map(|_| Some(Vec3::new(self.f32()?, self.f32()?, self.f32()?))).collect()
+    }
+
+    fn vec4s(&mut self) -> Option<Vec<Vec4>> {
+        let count = self.count(16)?;
+        (0..count).map(|_| self.vec4()).collect()
+    }
+}
+
+fn write_chunk<W: Write>(writer: &mut W, tag: &[u8; 4], chunk: ChunkWriter) -> std::io::Result<()> {
+    writer.write_all(tag)?;
+    writer.write_all(&(chunk.bytes.len() as u32).to_le_bytes())?;
+    writer.write_all(&crc32(&chunk.bytes).to_le_bytes())?;
+    writer.write_all(&chunk.bytes)
+}
+
+fn write_material(chunk: &mut ChunkWriter, name: &str, material: &MaterialDescriptor) {
+    let data = &material.data;
+    chunk.string(name);
+    for vector in [data.color, data.specular, data.field3, data.field4] {
+        chunk.f32s(&vector.to_array());
+    }
+    chunk.u32(material.maps.len() as u32);
+    for map in &material.maps {
+        chunk.string(map);
+    }
+    let mut embedded: Vec<_> = material.map_bytes.iter().collect();
+    embedded.sort_by_key(|(slot, _)| **slot);
+    chunk.u32(embedded.len() as u32);
+    for (slot, bytes) in embedded {
+        chunk.u32(*slot as u32);
+        chunk.u32(bytes.len() as u32);
+        chunk.bytes.extend(bytes.iter());
+    }
+    chunk.optional_string(material.shader.as_deref());
+}
+
+fn read_material(chunk: &mut ChunkReader) -> Option<(String, MaterialDescriptor)> {
+    let name = chunk.string()?;
+    let mut material = MaterialDescriptor::new();
+    material.data = MaterialData {
+        color: chunk.vec4()?,
+        specular: chunk.vec4()?,
+        field3: chunk.vec4()?,
+        field4: chunk.vec4()?,
+    };
+    for _ in 0..chunk.count(4)? {
+        material.maps.push(chunk.string()?);
+    }
+    for _ in 0..chunk.count(8)? {
+        let slot = chunk.u32()? as usize;
+        let len = chunk.count(1)?;
+        material.map_bytes.insert(slot, chunk.take(len)?.into());
+    }
+    material.shader = chunk.optional_string()?;
+    Some((name, material))
+}
+
+impl Mesh {
+    /// Write the mesh as a binary .rmesh file, see [`Self::load_binary()`]
+    ///
+    /// The modification times of the files the mesh was loaded from, its material libraries and textures included,
+    /// are stored along to tell whether the cache is still up to date
+    pub fn save_binary<W: Write>(&self, writer: &mut W) -> std::io::Result<()> {
+        let mut header = MAGIC.to_vec();
+        header.extend(VERSION.to_le_bytes());
+        header.extend(source_stamp(self).to_le_bytes());
+        header.extend(crc32(&header).to_le_bytes());
+        writer.write_all(&header)?;
+
+        let mut chunk = ChunkWriter::default();
+        chunk.u32(self.faces.len() as u32);
+        for index in &self.faces {
+            chunk.u32(*index);
+        }
+        chunk.bytes.push(self.point_cloud as u8);
+        write_chunk(writer, b"FACE", chunk)?;
+
+        for (tag, values) in [(b"VERT", &self.vertices), (b"NORM", &self.normals), (b"UVS_", &self.uvs)] {
+            let mut chunk = ChunkWriter::default();
+            chunk.vec3s(values);
+            write_chunk(writer, tag, chunk)?;
+        }
+        for (tag, values) in [(b"TANG", &self.tangents), (b"COLR", &self.colors)] {
+            let mut chunk = ChunkWriter::default();
+            chunk.vec4s(values);
+            write_chunk(writer, tag, chunk)?;
+        }
+
+        let mut chunk = ChunkWriter::default();
+        chunk.u32(self.groups.len() as u32);
+        for (start, name) in &self.groups {
+            chunk.u32(*start);
+            chunk.string(name);
+        }
+        write_chunk(writer, b"GRUP", chunk)?;
+
+        let mut chunk = ChunkWriter::default();
+        chunk.u32(self.materials.len() as u32);
+        for (start, name) in &self.materials {
+            chunk.u32(*start);
+            chunk.optional_string(name.as_deref());
+        }
+        let mut library: Vec<_> = self.material_sources.iter().collect();
+        library.sort_by_key(|(name, _)| *name);
+        chunk.u32(library.len() as u32);
+        for (name, material) in library {
+            write_material(&mut chunk, name, material);
+        }
+        write_chunk(writer, b"MATL", chunk)?;
+
+        let mut chunk = ChunkWriter::default();
+        chunk.u32(self.dependencies.len() as u32);
+        for path in &self.dependencies {
+            chunk.string(path);
+        }
+        write_chunk(writer, b"DEPS", chunk)?;
+
+        let mut chunk = ChunkWriter::default();
+        chunk.u32(self.bones.len() as u32);
+        for bone in &self.bones {
+            chunk.u32(bone.idx);
+            chunk.string(&bone.name);
+            chunk.f32s(&bone.pose.to_cols_array());
+            chunk.u32(bone.parent as u32);
+        }
+        chunk.u32(self.weights.len() as u32);
+        for weights in &self.weights {
+            chunk.u32(weights.len() as u32);
+            for (bone, weight) in weights {
+                chunk.u32(*bone);
+                chunk.f32s(&[*weight]);
+            }
+        }
+        write_chunk(writer, b"SKIN", chunk)
+    }
+
+    /// Load a mesh from a binary .rmesh file written by [`Self::save_binary()`]
+    ///
+    /// Files from another version of the format are rejected as unsupported,
+    /// corrupted ones fail their checksum
+    pub fn load_binary(file_name: &str) -> Result<Mesh, MeshParseError> {
+        match std::fs::read(file_name) {
+            Ok(bytes) => Self::load_binary_bytes(&bytes, file_name),
+            Err(why) => Err(MeshParseError::new(file_name, 0, 0, MeshParseErrorKind::Io(why))),
+        }
+    }
+
+    /// Load a mesh from .rmesh data in memory, see [`Self::load_binary()`]
+    pub fn load_binary_bytes(bytes: &[u8], name: &str) -> Result<Mesh, MeshParseError> {
+        Ok(Self::read_binary(bytes, name)?.0)
+    }
+
+    /// The mesh stored in .rmesh data, with the stamp of its source files
+    fn read_binary(bytes: &[u8], name: &str) -> Result<(Mesh, u64), MeshParseError> {
+        let error = |kind| MeshParseError::new(name, 0, 0, kind);
+        let header = bytes.get(..HEADER_SIZE).ok_or_else(|| error(MeshParseErrorKind::MissingValue))?;
+        let mut reader = ChunkReader { bytes: header, offset: 4 };
+        let (version, stamp, checksum) = (reader.u32(), reader.u64(), reader.u32());
+        if &header[..4] != MAGIC || version != Some(VERSION) {
+            return Err(error(MeshParseErrorKind::UnsupportedFormat));
+        }
+        if checksum != Some(crc32(&header[..HEADER_SIZE - 4])) {
+            return Err(error(MeshParseErrorKind::InvalidChecksum));
+        }
+        let stamp = stamp.unwrap_or(0);
+
+        let mut mesh = Mesh::new();
+        mesh.path = name.into();
+        let mut chunks = ChunkReader { bytes, offset: HEADER_SIZE };
+        while chunks.offset < bytes.len() {
+            let (tag, len, checksum) = (chunks.take(4), chunks.count(1), chunks.u32());
+            let (Some(tag), Some(len), Some(checksum)) = (tag, len, checksum) else {
+                return Err(error(MeshParseErrorKind::MissingValue));
+            };
+            let payload = chunks.take(len).ok_or_else(|| error(MeshParseErrorKind::MissingValue))?;
+            if crc32(payload) != checksum {
+                return Err(error(MeshParseErrorKind::InvalidChecksum));
+            }
+            let mut chunk = ChunkReader { bytes: payload, offset: 0 };
+            mesh.read_chunk(tag, &mut chunk).ok_or_else(|| error(MeshParseErrorKind::MissingValue))?;
+        }
+
+        let count = mesh.vertices.len();
+        let matches = |len: usize| len == 0 || len == count;
+        if ![mesh.normals.len(), mesh.uvs.len(), mesh.tangents.len(), mesh.colors.len(), mesh.weights.len()]
+            .into_iter()
+            .all(matches)
+        {
+            return Err(error(MeshParseErrorKind::MissingValue));
+        }
+        if mesh.faces.iter().any(|&index| index as usize >= count) {
+            return Err(error(MeshParseErrorKind::IndexOutOfRange));
+        }
+        mesh.update_bounds();
+        Ok((mesh, stamp))
+    }
+
+    fn read_chunk(&mut self, tag: &[u8], chunk: &mut ChunkReader) -> Option<()> {
+        match tag {
+            b"FACE" => {
+                let count = chunk.count(4)?;
+                self.faces = (0..count).map(|_| chunk.u32()).collect::<Option<_>>()?;
+                self.point_cloud = chunk.u8()? != 0;
+            }
+            b"VERT" => self.vertices = chunk.vec3s()?,
+            b"NORM" => self.normals = chunk.vec3s()?,
+            b"UVS_" => self.uvs = chunk.vec3s()?,
+            b"TANG" => self.tangents = chunk.vec4s()?,
+            b"COLR" => self.colors = chunk.vec4s()?,
+            b"GRUP" => {
+                let count = chunk.count(8)?;
+                self.groups = (0..count).map(|_| Some((chunk.u32()?, chunk.string()?))).collect::<Option<_>>()?;
+            }
+            b"MATL" => {
+                let count = chunk.count(5)?;
+                self.materials = (0..count)
+                    .map(|_| Some((chunk.u32()?, chunk.optional_string()?)))
+                    .collect::<Option<_>>()?;
+                let count = chunk.count(4)?;
+                self.material_sources = (0..count).map(|_| read_material(chunk)).collect::<Option<MaterialLibrary>>()?;
+            }
+            b"DEPS" => {
+                let count = chunk.count(4)?;
+                self.dependencies = (0..count).map(|_| chunk.string()).collect::<Option<_>>()?;
+            }
+            b"SKIN" => {
+                let count = chunk.count(76)?;
+                self.bones = (0..count)
+                    .map(|_| {
+                        let (idx, name) = (chunk.u32()?, chunk.string()?);
+                        let pose: Vec<f32> = (0..16).map(|_| chunk.f32()).collect::<Option<_>>()?;
+                        let parent = chunk.u32()? as i32;
+                        Some(Bone { idx, name, pose: Mat4::from_cols_slice(&pose), parent })
+                    })
+                    .collect::<Option<_>>()?;
+                let count = chunk.count(4)?;
+                self.weights = (0..count)
+                    .map(|_| {
+                        let influences = chunk.count(8)?;
+                        (0..influences).map(|_| Some((chunk.u32()?, chunk.f32()?))).collect()
+                    })
+                    .collect::<Option<_>>()?;
+            }
+            _ => {}
+        }
+        Some(())
+    }
+
+    /// Load a mesh file through its .rmesh cache, stored next to it
+    ///
+    /// the cache is used as long as the modification times of the file, of its material libraries
+    /// and of its textures match the ones it was written for, otherwise the file is parsed and the cache rewritten.
+    /// A cache that can't be written isn't fatal, the error is listed in [`Self::warnings()`].
+    /// .rmesh files are loaded directly
+    pub(crate) fn from_file_cached(file_name: &str) -> Result<Mesh, MeshParseError> {
+        let is_cache = Path::new(file_name)
+            .extension()
+            .is_some_and(|extension| extension.eq_ignore_ascii_case("rmesh"));
+        if is_cache {
+            return Self::load_binary(file_name);
+        }
+        let cache = cache_path(file_name);
+        if let Ok((mut mesh, stamp)) = std::fs::read(&cache)
+            .map_err(|why| MeshParseError::new(&cache, 0, 0, MeshParseErrorKind::Io(why)))
+            .and_then(|bytes| Self::read_binary(&bytes, &cache))
+        {
+            mesh.path = file_name.into();
+            if stamp != 0 && stamp == source_stamp(&mesh) {
+                return Ok(mesh);
+            }
+        }
+        let mut mesh = Self::from_file(file_name)?;
+        let written = std::fs::File::create(&cache).and_then(|file| {
+            let mut writer = std::io::BufWriter::new(file);
+            mesh.save_binary(&mut writer)?;
+            writer.flush()
+        });
+        if let Err(why) = written {
+            mesh.warnings.push(MeshParseError::new(&cache, 0, 0, MeshParseErrorKind::Io(why)));
+        }
+        Ok(mesh)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mesh::primitives;
+
+    fn saved(mesh: &Mesh) -> Vec<u8> {
+        let mut bytes = vec![];
+        mesh.save_binary(&mut bytes).unwrap();
+        bytes
+    }
+
+    fn load_error(bytes: &[u8]) -> MeshParseError {
+        match Mesh::load_binary_bytes(bytes, "test.rmesh") {
+            Ok(_) => panic!("invalid rmesh data loaded"),
+            Err(why) => why,
+        }
+    }
+
+    #[test]
+    fn meshes_survive_a_round_trip() {
+        let mut mesh = primitives::cube(Vec3::ONE);
+        mesh.generate_tangents();
+        mesh.groups = vec![(0, "top".into()), (6, "sides".into())];
+        mesh.materials = vec![(0, None), (6, Some("paint".into()))];
+        let mut paint = MaterialDescriptor::new();
+        paint.data.color = Vec4::new(1., 0., 0., 1.);
+        paint.set_map_bytes(0, "cube.glb#image0", &[1, 2, 3]);
+        mesh.material_sources.insert("paint".into(), paint);
+        mesh.dependencies = vec!["cube.mtl".into()];
+
+        let loaded = Mesh::load_binary_bytes(&saved(&mesh), "cube.rmesh").unwrap();
+        assert_eq!(loaded.faces, mesh.faces);
+        assert_eq!(loaded.vertices, mesh.vertices);
+        assert_eq!(loaded.normals, mesh.normals);
+        assert_eq!(loaded.uvs, mesh.uvs);
+        assert_eq!(loaded.tangents, mesh.tangents);
+        assert_eq!(loaded.groups, mesh.groups);
+        assert_eq!(loaded.materials, mesh.materials);
+        assert_eq!(loaded.dependencies, mesh.dependencies);
+        assert_eq!(loaded.aabb(), mesh.aabb());
+        let paint = &loaded.material_sources["paint"];
+        assert_eq!(paint.data.color, Vec4::new(1., 0., 0., 1.));
+        assert_eq!(paint.maps[0], "cube.glb#image0");
+        assert_eq!(*paint.map_bytes[&0], [1, 2, 3]);
+    }
+
+    #[test]
+    fn corrupted_files_are_rejected() {
+        let bytes = saved(&primitives::cube(Vec3::ONE));
+
+        let mut corrupted = bytes.clone();
+        let last = corrupted.len() - 1;
+        corrupted[last] ^= 0xFF;
+        assert!(matches!(load_error(&corrupted).kind, MeshParseErrorKind::InvalidChecksum));
+
+        let mut corrupted = bytes.clone();
+        corrupted[8] ^= 0xFF;
+        assert!(matches!(load_error(&corrupted).kind, MeshParseErrorKind::InvalidChecksum));
+
+        let mut other_version = bytes.clone();
+        other_version[4..8].copy_from_slice(&(VERSION + 1).to_le_bytes());
+        assert!(matches!(load_error(&other_version).kind, MeshParseErrorKind::UnsupportedFormat));
+
+        assert!(matches!(load_error(&bytes[..bytes.len() - 1]).kind, MeshParseErrorKind::MissingValue));
+    }
+
+    #[test]
+    fn caches_follow_their_dependencies() {
+        let dir = std::env::temp_dir().join(format!("rend_ox_rmesh_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let (obj, mtl) = (dir.join("triangle.obj"), dir.join("triangle.mtl"));
+        std::fs::write(&obj, "mtllib triangle.mtl\nv 0 0 0\nv 1 0 0\nv 0 1 0\nusemtl paint\nf 1 2 3\n").unwrap();
+        std::fs::write(&mtl, "newmtl paint\nKd 1 0 0\n").unwrap();
+        let file_name = obj.to_string_lossy();
+        let cache = cache_path(&file_name);
+
+        let mesh = Mesh::from_file_cached(&file_name).unwrap();
+        assert!(mesh.warnings().is_empty());
+        let (_, stamp) = Mesh::read_binary(&std::fs::read(&cache).unwrap(), &cache).unwrap();
+        assert_eq!(stamp, source_stamp(&mesh));
+
+        // editing the material library alone invalidates the cache
+        std::fs::write(&mtl, "newmtl paint\nKd 0 1 0\n").unwrap();
+        let later = std::time::SystemTime::now() + std::time::Duration::from_secs(10);
+        std::fs::File::options().write(true).open(&mtl).unwrap().set_modified(later).unwrap();
+        let mesh = Mesh::from_file_cached(&file_name).unwrap();
+        assert_eq!(mesh.material_sources["paint"].data.color.truncate(), Vec3::Y);
+
+        // caches are loaded as they are, without a cache of their own
+        let cached = Mesh::from_file_cached(&cache).unwrap();
+        assert_eq!(cached.faces, mesh.faces);
+        assert!(!Path::new(&cache_path(&cache)).exists());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
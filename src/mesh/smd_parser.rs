@@ -1,219 +1,517 @@
-use std::fs::{File, OpenOptions};
+//! Valve SMD parser
+//!
+//! Loads reference meshes: the `nodes` and `skeleton` blocks give the bones and their rest pose,
+//! the `triangles` block gives skinned faces, each tagged with a material.
+//! Animation smds only hold `nodes` and `skeleton` blocks, with a `time` per frame
+
+use std::collections::HashMap;
+use std::fs::OpenOptions;
 use std::io::{BufRead, BufReader};
-use std::collections::hash_map::HashMap;
-use std::collections::HashSet;
+use std::path::Path;
+
+use glam::{EulerRot, Mat4, Quat};
+
+use crate::animation::{AnimationClip, BoneKeyframe};
+use crate::error::{MeshParseError, MeshParseErrorKind};
+use crate::material::{MaterialDescriptor, DIFFUSE_MAP};
+use crate::Vec3;
 
-use super::Triangle;
-use super::Mesh;
-use super::Bone;
+use super::mtl_parser::MaterialLibrary;
+use super::obj_parser::{column, LineResult};
+use super::solver::solve_indices;
+use super::{Aabb, Bone, BoundingSphere, Mesh, Triangle};
 
-struct Rig {
-    pub(crate) groups: HashMap<u32, String>,
-    pub(crate) bones: HashMap<u32, Bone>,
+/// Position and rotation (euler angles, in radians) of a bone at a given frame, relative to its parent
+type BoneKey = (u32, Vec3, Vec3);
+
+/// bits of a position and of its bone weights
+type VertexKey = ([u32; 3], Vec<(u32, u32)>);
+
+#[derive(PartialEq)]
+enum Block {
+    Root,
+    Nodes,
+    Skeleton,
+    Triangles,
 }
 
-impl Rig {
-    pub fn new() -> Rig {
-        Rig {
-            groups: HashMap::new(),
-            bones: HashMap::new(),
-        }
+pub(crate) struct SMDMesh {
+    pub(crate) bones: Vec<Bone>,
+    /// bone keys of each `time` of the skeleton block, with that time
+    pub(crate) frames: Vec<(i32, Vec<BoneKey>)>,
+    pub(crate) vertices: Vec<Vec3>,
+    pub(crate) uvs: Vec<Vec3>,
+    pub(crate) normals: Vec<Vec3>,
+    pub(crate) weights: Vec<Vec<(u32, f32)>>,
+    pub(crate) triangles: Vec<Triangle>,
+    pub(crate) materials: Vec<String>,
+    block: Block,
+    corner: usize,
+    // corners sharing a value are stored once, weights being part of a position
+    vertex_keys: HashMap<VertexKey, usize>,
+    uv_keys: HashMap<[u32; 3], usize>,
+    normal_keys: HashMap<[u32; 3], usize>,
+}
+
+/// SMD rotations are euler angles applied around X, then Y, then Z
+fn bone_rotation(rotation: Vec3) -> Quat {
+    Quat::from_euler(EulerRot::ZYX, rotation.z, rotation.y, rotation.x)
+}
+
+/// The local transform of a bone from its SMD position and rotation
+fn bone_transform(position: Vec3, rotation: Vec3) -> Mat4 {
+    Mat4::from_rotation_translation(bone_rotation(rotation), position)
+}
+
+fn intern(keys: &mut HashMap<[u32; 3], usize>, values: &mut Vec<Vec3>, value: Vec3) -> usize {
+    *keys.entry(value.to_array().map(f32::to_bits)).or_insert_with(|| {
+        values.push(value);
+        values.len() - 1
+    })
+}
+
+fn parse_value<T: std::str::FromStr>(line: &str, token: Option<&str>) -> LineResult<T> {
+    match token {
+        Some(token) => token
+            .parse::<T>()
+            .map_err(|_| (column(line, token), MeshParseErrorKind::InvalidNumber)),
+        None => Err((line.len() + 1, MeshParseErrorKind::MissingValue)),
     }
 }
 
-impl Mesh {
-    pub fn load_smd(&mut self, file_name: &str) -> bool {
-        let file = OpenOptions::new().read(true).open(file_name);
+fn parse_vec3<'a>(line: &str, iter: &mut impl Iterator<Item = &'a str>) -> LineResult<Vec3> {
+    Ok(Vec3::new(
+        parse_value(line, iter.next())?,
+        parse_value(line, iter.next())?,
+        parse_value(line, iter.next())?,
+    ))
+}
 
-        if let Ok(obj) = file {
-            self.smd_load_nodes(&obj);
-            self.smd_load_skeleton(&obj);
-            self.smd_load_triangles(&obj);
-        } else {
-            println!("Cant open \"{}\" mesh file!", file_name);
+impl SMDMesh {
+    pub fn new() -> SMDMesh {
+        SMDMesh {
+            bones: vec![],
+            frames: vec![],
+            vertices: vec![],
+            uvs: vec![],
+            normals: vec![],
+            weights: vec![],
+            triangles: vec![],
+            materials: vec![],
+            block: Block::Root,
+            corner: 0,
+            vertex_keys: HashMap::new(),
+            uv_keys: HashMap::new(),
+            normal_keys: HashMap::new(),
         }
-        true
     }
 
-    fn smd_parse_node(&self, &mut rig_data : &mut Rig, line: String) -> bool {
-        let idx : u32;
-        let name : str;
-        let parent : i32;
+    pub fn load_smd(&mut self, file_name: &str) -> Result<(), MeshParseError> {
+        match OpenOptions::new().read(true).open(file_name) {
+            Ok(smd) => self.load_smd_reader(BufReader::new(smd), file_name),
+            Err(why) => Err(MeshParseError::new(file_name, 0, 0, MeshParseErrorKind::Io(why))),
+        }
+    }
 
-        let mut iter = line.split_ascii_whitespace().filter(|&x| !x.is_empty());
+    /// Parse smd data from any reader, `file_name` is used in errors
+    pub fn load_smd_reader<R: BufRead>(&mut self, reader: R, file_name: &str) -> Result<(), MeshParseError> {
+        for (line_idx, option_line) in reader.lines().enumerate() {
+            let line = option_line.map_err(|why| {
+                MeshParseError::new(file_name, line_idx + 1, 0, MeshParseErrorKind::Io(why))
+            })?;
+            self.parse_line(&line).map_err(|(column, kind)| {
+                MeshParseError::new(file_name, line_idx + 1, column, kind)
+            })?;
+        }
+        if let Some((_, keys)) = self.frames.first() {
+            for (idx, position, rotation) in keys {
+                if let Some(bone) = self.bones.iter_mut().find(|bone| bone.idx == *idx) {
+                    bone.pose = bone_transform(*position, *rotation);
+                }
+            }
+        }
+        self.triangles.sort_by_key(|triangle| triangle.material);
+        Ok(())
+    }
 
-        if let Some(word) = iter.next() {
-            if let Ok(parse) = word.parse::<u32>() {
-                idx = parse as u32;
-            } else {
-                return false;
-            };
-        } else {
-            return false;
+    fn parse_line(&mut self, line: &str) -> LineResult<()> {
+        let statement = line.trim();
+        if statement.is_empty() || statement.starts_with("//") {
+            return Ok(());
         }
+        if statement == "end" && self.block != Block::Root {
+            if self.block == Block::Triangles && self.corner != 0 {
+                return Err((column(line, statement), MeshParseErrorKind::MissingValue));
+            }
+            self.block = Block::Root;
+            return Ok(());
+        }
+        match self.block {
+            Block::Root => match statement.split_ascii_whitespace().next() {
+                Some("version") => {}
+                Some("nodes") => self.block = Block::Nodes,
+                Some("skeleton") => self.block = Block::Skeleton,
+                Some("triangles") => self.block = Block::Triangles,
+                _ => return Err((column(line, statement), MeshParseErrorKind::UnsupportedStatement)),
+            },
+            Block::Nodes => self.parse_node(line, statement)?,
+            Block::Skeleton => self.parse_skeleton_key(line, statement)?,
+            Block::Triangles => self.parse_triangle_line(line, statement)?,
+        }
+        Ok(())
+    }
 
-        if let Some(word) = iter.next() {
-            name = word.into();
-        } else {
-            return false;
+    /// `<index> "<name>" <parent index>`, parents being declared before their children
+    fn parse_node(&mut self, line: &str, statement: &str) -> LineResult<()> {
+        let mut iter = statement.split_ascii_whitespace();
+        let idx: u32 = parse_value(line, iter.next())?;
+        let parent_token = statement.split_ascii_whitespace().last();
+        let parent: i32 = parse_value(line, parent_token)?;
+        if parent >= idx as i32 || parent < -1 {
+            return Err((column(line, parent_token.unwrap_or(statement)), MeshParseErrorKind::IndexOutOfRange));
         }
+        let name = match (statement.find('"'), statement.rfind('"')) {
+            (Some(start), Some(end)) if start < end => &statement[start + 1..end],
+            _ => return Err((column(line, statement), MeshParseErrorKind::MissingValue)),
+        };
+        self.bones.push(Bone {
+            idx,
+            name: name.into(),
+            pose: Mat4::IDENTITY,
+            parent,
+        });
+        Ok(())
+    }
 
-        if let Some(word) = iter.next() {
-            if let Ok(parse) = word.parse::<i32>() {
-                if parent < idx as i32 {
-                    parent = parse as i32;
-                } else {
-                    return false;
+    /// `time <frame>` followed by `<bone> <px> <py> <pz> <rx> <ry> <rz>` lines
+    fn parse_skeleton_key(&mut self, line: &str, statement: &str) -> LineResult<()> {
+        let mut iter = statement.split_ascii_whitespace();
+        if statement.starts_with("time") {
+            iter.next();
+            let time: i32 = parse_value(line, iter.next())?;
+            self.frames.push((time, vec![]));
+            return Ok(());
+        }
+        let bone_token = iter.next();
+        let bone: u32 = parse_value(line, bone_token)?;
+        if !self.bones.iter().any(|known| known.idx == bone) {
+            return Err((column(line, bone_token.unwrap_or(statement)), MeshParseErrorKind::IndexOutOfRange));
+        }
+        let position = parse_vec3(line, &mut iter)?;
+        let rotation = parse_vec3(line, &mut iter)?;
+        match self.frames.last_mut() {
+            Some((_, keys)) => keys.push((bone, position, rotation)),
+            None => return Err((column(line, statement), MeshParseErrorKind::MissingValue)),
+        }
+        Ok(())
+    }
+
+    /// A material name followed by three corners:
+    /// `<parent bone> <px> <py> <pz> <nx> <ny> <nz> <u> <v> [<links> (<bone> <weight>)*]`
+    fn parse_triangle_line(&mut self, line: &str, statement: &str) -> LineResult<()> {
+        if self.corner == 0 {
+            let slot = match self.materials.iter().position(|material| material == statement) {
+                Some(slot) => slot,
+                None => {
+                    self.materials.push(statement.into());
+                    self.materials.len() - 1
                 }
-            } else {
-                return false;
             };
-        } else {
-            return false;
+            let mut triangle = Triangle::new();
+            triangle.material = Some(slot);
+            triangle.textures = Some([0; 3]);
+            triangle.normals = Some([0; 3]);
+            self.triangles.push(triangle);
+            self.corner = 1;
+            return Ok(());
         }
 
-        rig_data.groups.push(idx, name);
-        rig_data.bones.push(idx, Bone {
-            idx,
-            pose : Mat4::IDENTITY,
-            parent,
+        let mut iter = statement.split_ascii_whitespace();
+        let parent = self.parse_bone(line, iter.next())?;
+        let position = parse_vec3(line, &mut iter)?;
+        let normal = parse_vec3(line, &mut iter)?;
+        let uv = Vec3::new(parse_value(line, iter.next())?, parse_value(line, iter.next())?, 0.);
+
+        let mut weights: Vec<(u32, f32)> = vec![];
+        if let Some(links) = iter.next() {
+            let links: usize = parse_value(line, Some(links))?;
+            for _ in 0..links {
+                let bone = self.parse_bone(line, iter.next())?;
+                let weight: f32 = parse_value(line, iter.next())?;
+                weights.push((bone, weight));
+            }
+        }
+        // the parent bone takes whatever weight the links leave
+        let remaining = 1. - weights.iter().map(|(_, weight)| weight).sum::<f32>();
+        if remaining > f32::EPSILON {
+            weights.push((parent, remaining));
+        }
+
+        let key = (
+            position.to_array().map(f32::to_bits),
+            weights.iter().map(|(bone, weight)| (*bone, weight.to_bits())).collect(),
+        );
+        let vertex = *self.vertex_keys.entry(key).or_insert_with(|| {
+            self.vertices.push(position);
+            self.weights.push(weights);
+            self.vertices.len() - 1
         });
+        let uv = intern(&mut self.uv_keys, &mut self.uvs, uv);
+        let normal = intern(&mut self.normal_keys, &mut self.normals, normal.normalize_or_zero());
 
-        false
-    }
-
-    fn smd_load_nodes(&mut self, &file: &File) -> bool {
-        let mut rig_data = Rig::new();
-        for option_line in BufReader::new(file).lines() {
-            match option_line {
-                Err(why) => panic!("{:?}", why),
-                Ok(line) => match line {
-                    s if s.chars().all(|x| x.is_ascii_whitespace()) => {
-                        continue;
-                    }
-                    s if s.starts_with("end") => {
-                        break;
-                    }
-                    s => {
-                        if !self.smd_parse_node(&mut rig_data, s) {
-                            return false;
-                        }
-                    },
-                }
+        let corner = self.corner - 1;
+        if let Some(triangle) = self.triangles.last_mut() {
+            triangle.points[corner] = vertex;
+            if let Some(textures) = triangle.textures.as_mut() {
+                textures[corner] = uv;
+            }
+            if let Some(normals) = triangle.normals.as_mut() {
+                normals[corner] = normal;
             }
         }
+        self.corner = (self.corner + 1) % 4;
+        Ok(())
+    }
 
-        self.groups = rig_data.groups.into();
-        self.bones = rig_data.bones.into();
-        self.groups.sort();
-        self.bones.sort();
-
-        true
-    }
-
-    fn smd_load_skeleton(&mut self, &file: &File) -> bool {
-        true
-    }
-
-    fn smd_parse_corner(&self, &mut tri: &mut Triangle, state : u32, line : String) -> bool {
-        false
-    }
-
-    fn smd_load_triangles(&mut self, &file: &File) -> bool {
-        let mut materials = HashMap!();
-        let mut mat_count = 0;
-        let mut curr_material : Option<u32> = None;
-        let mut state = 0;
-        let mut tri = Triangle::new();
-        for option_line in BufReader::new(file).lines() {
-            match option_line {
-                Err(why) => panic!("{:?}", why),
-                Ok(line) => match line {
-                    s if s.chars().all(|x| x.is_ascii_whitespace()) => {
-                        continue;
-                    }
-                    s if s.starts_with("end") => {
-                        break;
-                    }
-                    s => {
-                        if state == 0 {
-                            curr_material = materials.entry(&s).or_insert(materials.len);
-                        } else if state > 3 {
-                            return false;
-                        } else {
-                            if !self.smd_parse_corner(&mut tri, state, s) {
-                                return false;
-                            }
-                        }
-                        state += 1;
-                    },
-                }
+    fn parse_bone(&self, line: &str, token: Option<&str>) -> LineResult<u32> {
+        let bone: u32 = parse_value(line, token)?;
+        if !self.bones.iter().any(|known| known.idx == bone) {
+            return Err((column(line, token.unwrap_or(line)), MeshParseErrorKind::IndexOutOfRange));
+        }
+        Ok(bone)
+    }
+
+    /// Material slots of the faces, textures named after the materials are used when found next to the smd
+    fn material_library(&self, file_name: &str) -> MaterialLibrary {
+        let dir = Path::new(file_name).parent().unwrap_or(Path::new(""));
+        let mut library = MaterialLibrary::new();
+        for name in &self.materials {
+            let mut material = MaterialDescriptor::new();
+            let texture = dir.join(name);
+            if texture.is_file() {
+                material.set_map(DIFFUSE_MAP, &texture.to_string_lossy());
             }
+            library.insert(name.clone(), material);
         }
-        true
+        library
     }
 }
 
-/*
-
-for option_line in BufReader::new(obj).lines() {
-                match option_line {
-                    Err(why) => panic!("{:?}", why),
-                    Ok(line) => match line {
-                        s if s.chars().all(|x| x.is_ascii_whitespace()) => {
-                            continue;
-                        }
-                        s if s.starts_with('#') => {
-                            continue;
-                        }
-                        s if s.starts_with("o ") => {
-                            continue;
-                        }
-                        s if s.starts_with("s ") => {
-                            continue;
-                        }
-                        s if s.starts_with("usemtl ") => {
-                            continue;
-                        }
-                        s if s.starts_with("mtllib ") => {
-                            continue;
-                        }
-                        s if s.starts_with("v ") => {
-                            if let Some(vertex) = self.parse_vertex(s) {
-                                self.vertices.push(vertex);
-                            } else {
-                                println!("Invalid vertexes in \"{}\" !", file_name);
-                                return false;
-                            }
-                        }
-                        s if s.starts_with("vt ") => {
-                            if let Some(vertex) = self.parse_uvw(s) {
-                                self.uvs.push(vertex);
-                            } else {
-                                println!("Invalid uv in \"{}\" !", file_name);
-                                return false;
-                            }
-                        }
-                        s if s.starts_with("vn ") => {
-                            if let Some(normal) = self.parse_normal(s) {
-                                self.normals.push(normal);
-                            } else {
-                                println!("Invalid normal in \"{}\" !", file_name);
-                                return false;
-                            }
-                        }
-                        s if s.starts_with("f ") => {
-                            if !self.parse_face(s) {
-                                println!("Invalid face in \"{}\" !", file_name);
-                                return false;
-                            }
-                        }
-                        other => {
-                            println!("Invalid \"{file_name}\" mesh file!, \"{other}\" string not supported");
-                            return false;
-                        }
-                    },
+impl Mesh {
+    /// Load a reference mesh from an ASCII .smd file
+    /// This function will return an error locating the issue on an unparsable file
+    pub fn from_smd(file_name: &str) -> Result<Mesh, MeshParseError> {
+        let mut smd = SMDMesh::new();
+        smd.load_smd(file_name)?;
+        Ok(Self::from_smd_mesh(smd, file_name))
+    }
+
+    /// Load a reference mesh from any reader over ASCII smd data
+    ///
+    /// `name` identifies the mesh, textures are looked up relative to it
+    pub fn from_smd_reader<R: BufRead>(reader: R, name: &str) -> Result<Mesh, MeshParseError> {
+        let mut smd = SMDMesh::new();
+        smd.load_smd_reader(reader, name)?;
+        Ok(Self::from_smd_mesh(smd, name))
+    }
+
+    fn from_smd_mesh(mut smd: SMDMesh, path: &str) -> Mesh {
+        let (vertices, uvs, normals, faces, sources) =
+            solve_indices(&smd.vertices, &smd.uvs, &smd.normals, &smd.triangles, None);
+        let mut materials: Vec<(u32, Option<String>)> = vec![];
+        for (i, triangle) in smd.triangles.iter().enumerate() {
+            let name = triangle.material.map(|slot| smd.materials[slot].clone());
+            if materials.last().is_none_or(|(_, last)| *last != name) {
+                materials.push(((i * 3) as u32, name));
+            }
+        }
+        smd.bones.sort_by_key(|bone| bone.idx);
+        let (aabb, bounding_sphere) = (Aabb::from_points(&vertices), BoundingSphere::from_points(&vertices));
+        Mesh {
+            path: path.into(),
+            faces: faces.iter().map(|x| *x as u32).collect(),
+            vertices,
+            uvs,
+            normals,
+            tangents: vec![],
+            colors: vec![],
+            point_cloud: false,
+            aabb,
+            bounding_sphere,
+            materials,
+            material_sources: smd.material_library(path),
+            groups: vec![],
+            weights: sources.iter().map(|&src| smd.weights[src].clone()).collect(),
+            bones: std::mem::take(&mut smd.bones),
+            dependencies: vec![],
+            warnings: vec![],
+        }
+    }
+}
+
+/// Frame rates that are zero, negative or not finite would put every frame at the same time, or at an invalid one
+fn check_fps(file_name: &str, fps: f32) -> Result<(), MeshParseError> {
+    if fps.is_finite() && fps > 0. {
+        Ok(())
+    } else {
+        Err(MeshParseError::new(file_name, 0, 0, MeshParseErrorKind::InvalidNumber))
+    }
+}
+
+impl AnimationClip {
+    /// Load an animation sequence from an ASCII .smd file, against the skeleton of `mesh`
+    ///
+    /// Bones are matched by name, those missing from the skeleton are ignored.
+    /// SMD frames carry no timing, they are spread at `fps` frames per second,
+    /// which must be positive for the frames to be told apart
+    pub fn from_smd(file_name: &str, mesh: &Mesh, fps: f32) -> Result<AnimationClip, MeshParseError> {
+        check_fps(file_name, fps)?;
+        let mut smd = SMDMesh::new();
+        smd.load_smd(file_name)?;
+        Ok(Self::from_smd_mesh(smd, file_name, mesh, fps))
+    }
+
+    /// Load an animation sequence from any reader over ASCII smd data, see [`Self::from_smd()`]
+    pub fn from_smd_reader<R: BufRead>(reader: R, name: &str, mesh: &Mesh, fps: f32) -> Result<AnimationClip, MeshParseError> {
+        check_fps(name, fps)?;
+        let mut smd = SMDMesh::new();
+        smd.load_smd_reader(reader, name)?;
+        Ok(Self::from_smd_mesh(smd, name, mesh, fps))
+    }
+
+    fn from_smd_mesh(smd: SMDMesh, path: &str, mesh: &Mesh, fps: f32) -> AnimationClip {
+        let name = Path::new(path)
+            .file_stem()
+            .map_or(path.into(), |stem| stem.to_string_lossy());
+        let mut clip = AnimationClip::new(&name);
+        let skeleton: HashMap<u32, &Bone> = smd
+            .bones
+            .iter()
+            .filter_map(|node| {
+                let bone = mesh.bones().iter().find(|bone| bone.name() == node.name())?;
+                Some((node.idx(), bone))
+            })
+            .collect();
+        let start = smd.frames.iter().map(|(time, _)| *time).min().unwrap_or(0);
+
+        for (time, keys) in &smd.frames {
+            for (node, position, rotation) in keys {
+                if let Some(bone) = skeleton.get(node) {
+                    let keyframe = BoneKeyframe {
+                        time: (time - start) as f32 / fps,
+                        translation: *position,
+                        rotation: bone_rotation(*rotation),
+                    };
+                    clip.add_keyframe(bone, keyframe);
                 }
             }
- // /*  */
- */
\ No newline at end of file
+        }
+        clip
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const REFERENCE: &str = "version 1
+nodes
+0 \"root\" -1
+1 \"arm\" 0
+end
+skeleton
+time 0
+0 0 0 0 0 0 0
+1 1 0 0 0 0 1.5707964
+time 1
+1 5 5 5 0 0 0
+end
+triangles
+metal
+1 0 0 0 0 0 1 0 0 1 0 0.25
+0 1 0 0 0 0 1 1 0
+0 0 1 0 0 0 1 0 1
+wood
+0 0 0 1 0 0 1 0 0
+0 1 0 1 0 0 1 1 0
+0 0 1 1 0 0 1 0 1
+metal
+0 0 0 2 0 0 1 0 0
+0 1 0 2 0 0 1 1 0
+0 0 1 2 0 0 1 0 1
+end
+";
+
+    fn load(smd: &str) -> Mesh {
+        Mesh::from_smd_reader(smd.as_bytes(), "test.smd").unwrap()
+    }
+
+    #[test]
+    fn leftover_weight_goes_to_the_parent_bone() {
+        let mesh = load(REFERENCE);
+        let weights = &mesh.weights()[mesh.faces[0] as usize];
+        assert_eq!(weights, &[(0, 0.25), (1, 0.75)]);
+        let weights = &mesh.weights()[mesh.faces[1] as usize];
+        assert_eq!(weights, &[(0, 1.)]);
+    }
+
+    #[test]
+    fn faces_are_grouped_by_material() {
+        let mesh = load(REFERENCE);
+        assert_eq!(mesh.faces.len(), 9);
+        assert_eq!(mesh.materials, [(0, Some("metal".into())), (6, Some("wood".into()))]);
+        // the second metal face comes before the wood one
+        assert_eq!(mesh.vertices[mesh.faces[3] as usize].z, 2.);
+        assert_eq!(mesh.vertices[mesh.faces[6] as usize].z, 1.);
+        assert!(mesh.material_sources.contains_key("wood"));
+    }
+
+    #[test]
+    fn rest_pose_is_the_first_frame() {
+        let mesh = load(REFERENCE);
+        let arm = &mesh.bones()[1];
+        assert_eq!((arm.name(), arm.parent()), ("arm", Some(0)));
+        let (_, rotation, translation) = arm.pose().to_scale_rotation_translation();
+        assert_eq!(translation, Vec3::X);
+        assert!(rotation.angle_between(Quat::from_rotation_z(std::f32::consts::FRAC_PI_2)) < 1e-3);
+        assert_eq!(mesh.bones()[0].pose(), Mat4::IDENTITY);
+    }
+
+    #[test]
+    fn errors_are_located() {
+        let smd = REFERENCE.replace("1 0 0 0 0 0 1 0 0 1 0 0.25", "1 0 0 0 0 0 1 0 0 1 7 0.25");
+        let error = match Mesh::from_smd_reader(smd.as_bytes(), "test.smd") {
+            Ok(_) => panic!("unknown bone loaded"),
+            Err(why) => why,
+        };
+        assert_eq!((error.line, error.column), (15, 21));
+        assert!(matches!(error.kind, MeshParseErrorKind::IndexOutOfRange));
+    }
+
+    #[test]
+    fn clips_match_bones_by_name() {
+        let mesh = load(REFERENCE);
+        let sequence = "version 1\nnodes\n0 \"arm\" -1\n1 \"tail\" 0\nend\nskeleton\ntime 10\n0 2 0 0 0 0 0\n1 0 0 0 0 0 0\ntime 12\n0 4 0 0 0 0 1.5707964\nend\n";
+        let clip = AnimationClip::from_smd_reader(sequence.as_bytes(), "anims/walk.smd", &mesh, 2.).unwrap();
+        assert_eq!(clip.name, "walk");
+        assert_eq!(clip.animated_bones().collect::<Vec<_>>(), [1]);
+
+        // frames are spread from the first one at the given rate
+        let keyframes = clip.keyframes(1);
+        assert_eq!(keyframes.iter().map(|keyframe| keyframe.time).collect::<Vec<_>>(), [0., 1.]);
+        assert_eq!(clip.duration, 1.);
+        assert_eq!(keyframes[1].translation, Vec3::new(4., 0., 0.));
+        assert_eq!(clip.sample(1, 0.5).map(|(translation, _)| translation), Some(Vec3::new(3., 0., 0.)));
+    }
+
+    #[test]
+    fn clips_need_a_positive_frame_rate() {
+        let mesh = load(REFERENCE);
+        for fps in [0., -24., f32::NAN] {
+            match AnimationClip::from_smd_reader(REFERENCE.as_bytes(), "walk.smd", &mesh, fps) {
+                Ok(_) => panic!("clip loaded at {fps} fps"),
+                Err(why) => assert!(matches!(why.kind, MeshParseErrorKind::InvalidNumber)),
+            }
+        }
+    }
+}
@@ -1,64 +1,296 @@
+use std::collections::HashMap;
+
+use glam::Vec4;
+
 use super::Triangle;
 use super::Vec3;
 
-#[derive(Clone)]
-struct VertBinding {
-    pub idx: usize,
-    pub uv: usize,
-    pub nm: usize,
-}
+/// Map each position to the first one lying within `epsilon` of it
+///
+/// Positions are bucketed in a grid of `epsilon` sized cells, so only the neighbouring cells are searched for each of them.
+/// Cells are computed in 64 bits, as small distances over large coordinates go past the range of `i32`.
+/// An `epsilon` that isn't a positive finite number only merges identical positions
+pub(crate) fn weld_positions(pos: &[Vec3], epsilon: f32) -> Vec<usize> {
+    if !(epsilon.is_finite() && epsilon > 0.) {
+        return unique_indices(pos);
+    }
+    let cell_of = |point: Vec3| (point.as_dvec3() / epsilon as f64).floor().to_array().map(|x| x as i64);
+    let mut cells: HashMap<[i64; 3], Vec<usize>> = HashMap::new();
+    let mut remap = Vec::with_capacity(pos.len());
 
-fn binding_find(
-    binding: &mut Vec<Vec<VertBinding>>,
-    idx: usize,
-    vp: usize,
-    uv: usize,
-    nm: usize,
-) -> (bool, usize) {
-    for corner in &binding[vp] {
-        if corner.uv == uv && corner.nm == nm {
-            return (true, corner.idx);
+    for (idx, point) in pos.iter().enumerate() {
+        let cell = cell_of(*point);
+        let mut found = None;
+        'search: for x in -1..=1 {
+            for y in -1..=1 {
+                for z in -1..=1 {
+                    let neighbour = [cell[0].saturating_add(x), cell[1].saturating_add(y), cell[2].saturating_add(z)];
+                    let Some(candidates) = cells.get(&neighbour) else {
+                        continue;
+                    };
+                    if let Some(other) = candidates
+                        .iter()
+                        .find(|&&other| pos[other].distance_squared(*point) <= epsilon * epsilon)
+                    {
+                        found = Some(*other);
+                        break 'search;
+                    }
+                }
+            }
         }
+        remap.push(match found {
+            Some(other) => other,
+            None => {
+                cells.entry(cell).or_default().push(idx);
+                idx
+            }
+        });
     }
+    remap
+}
 
-    binding[vp].push(VertBinding { idx, uv, nm });
-
-    (false, idx)
+/// Map each value to the first one strictly equal to it
+pub(crate) fn unique_indices(values: &[Vec3]) -> Vec<usize> {
+    let mut seen: HashMap<[u32; 3], usize> = HashMap::with_capacity(values.len());
+    values
+        .iter()
+        .enumerate()
+        .map(|(idx, value)| *seen.entry(value.to_array().map(f32::to_bits)).or_insert(idx))
+        .collect()
 }
 
+/// positions, uvs, normals, indices and the source position of each vertex
+pub(crate) type SolvedBuffers = (Vec<Vec3>, Vec<Vec3>, Vec<Vec3>, Vec<usize>, Vec<usize>);
+
+/// Build indexed buffers from faces, each unique (position, uv, normal) corner becoming a vertex
+///
+/// Vertices are emitted in the order their corners first appear in `faces`.
+/// When `weld` is given, positions closer than this distance are merged beforehand.
+/// Faces without uvs or normals use the first ones, or zero when there are none.
+/// The index in `pos` each vertex comes from is returned last, to carry per-position data along
 pub(crate) fn solve_indices(
-    pos: &Vec<Vec3>,
-    uvs: &Vec<Vec3>,
-    normals: &Vec<Vec3>,
-    faces: &Vec<Triangle>,
-) -> (Vec<Vec3>, Vec<Vec3>, Vec<Vec3>, Vec<usize>) {
+    pos: &[Vec3],
+    uvs: &[Vec3],
+    normals: &[Vec3],
+    faces: &[Triangle],
+    weld: Option<f32>,
+) -> SolvedBuffers {
+    let remap: Option<Vec<usize>> = weld.map(|epsilon| weld_positions(pos, epsilon));
     let mut out_vp: Vec<Vec3> = Vec::new();
     let mut out_nm: Vec<Vec3> = Vec::new();
     let mut out_uv: Vec<Vec3> = Vec::new();
-    let mut binding: Vec<Vec<VertBinding>> = vec![Vec::new(); pos.len()];
+    let mut out_src: Vec<usize> = Vec::new();
+    let mut binding: HashMap<(usize, usize, usize), usize> = HashMap::with_capacity(faces.len() * 3);
 
-    let mut out_faces: Vec<usize> = Vec::new();
-
-    let mut counter = 0;
+    let mut out_faces: Vec<usize> = Vec::with_capacity(faces.len() * 3);
 
     for face in faces {
+        let textures = face.textures.unwrap_or([0; 3]);
+        let face_normals = face.normals.unwrap_or([0; 3]);
         for i in 0..3 {
-            let (found, idx) = binding_find(
-                &mut binding,
-                counter,
-                face.points[i],
-                face.textures.unwrap_or([0; 3])[i],
-                face.normals.unwrap_or([0; 3])[i],
-            );
-            if !found {
-                counter += 1;
-                out_vp.push(pos[face.points[i]]);
-                out_uv.push(uvs[face.textures.unwrap_or([0; 3])[i]]);
-                out_nm.push(normals[face.normals.unwrap_or([0; 3])[i]]);
-            }
+            let vp = match &remap {
+                Some(remap) => remap[face.points[i]],
+                None => face.points[i],
+            };
+            let counter = binding.len();
+            let idx = *binding
+                .entry((vp, textures[i], face_normals[i]))
+                .or_insert_with(|| {
+                    out_vp.push(pos[vp]);
+                    out_src.push(vp);
+                    out_uv.push(uvs.get(textures[i]).copied().unwrap_or(Vec3::ZERO));
+                    out_nm.push(normals.get(face_normals[i]).copied().unwrap_or(Vec3::ZERO));
+                    counter
+                });
             out_faces.push(idx);
         }
     }
 
-    (out_vp, out_uv, out_nm, out_faces)
+    (out_vp, out_uv, out_nm, out_faces, out_src)
+}
+
+/// area weighted normals of each vertex of indexed triangles, for meshes lacking them
+pub(crate) fn smooth_normals(positions: &[Vec3], indices: &[u32]) -> Vec<Vec3> {
+    let mut normals = vec![Vec3::ZERO; positions.len()];
+    for face in indices.chunks_exact(3) {
+        let [a, b, c] = [face[0] as usize, face[1] as usize, face[2] as usize];
+        let normal = (positions[b] - positions[a]).cross(positions[c] - positions[a]);
+        for corner in [a, b, c] {
+            normals[corner] += normal;
+        }
+    }
+    normals.iter().map(|normal| normal.normalize_or_zero()).collect()
+}
+
+/// MikkTSpace style tangents of each vertex of indexed triangles, with the handedness of the bitangent in `w`
+///
+/// The tangent and bitangent of each face follow its uv gradients, they are projected on the plane of
+/// each corner normal and summed weighted by the corner angle.
+/// Vertices whose faces have no uv gradient get any tangent orthogonal to their normal,
+/// every vertex gets the `X` axis when uvs or normals don't match the positions
+pub(crate) fn generate_tangents(positions: &[Vec3], uvs: &[Vec3], normals: &[Vec3], indices: &[u32]) -> Vec<Vec4> {
+    if uvs.len() != positions.len() || normals.len() != positions.len() {
+        return vec![Vec4::new(1., 0., 0., 1.); positions.len()];
+    }
+    let mut tangents = vec![Vec3::ZERO; positions.len()];
+    let mut bitangents = vec![Vec3::ZERO; positions.len()];
+    for face in indices.chunks_exact(3) {
+        let corners = [face[0] as usize, face[1] as usize, face[2] as usize];
+        if corners.iter().any(|&corner| corner >= positions.len()) {
+            continue;
+        }
+        let [a, b, c] = corners;
+        let (edge_b, edge_c) = (positions[b] - positions[a], positions[c] - positions[a]);
+        let (uv_b, uv_c) = (uvs[b] - uvs[a], uvs[c] - uvs[a]);
+        let det = uv_b.x * uv_c.y - uv_c.x * uv_b.y;
+        if det.abs() <= f32::EPSILON {
+            continue;
+        }
+        let tangent = (edge_b * uv_c.y - edge_c * uv_b.y) / det;
+        let bitangent = (edge_c * uv_b.x - edge_b * uv_c.x) / det;
+        for i in 0..3 {
+            let corner = corners[i];
+            let point = positions[corner];
+            let angle = (positions[corners[(i + 1) % 3]] - point).angle_between(positions[corners[(i + 2) % 3]] - point);
+            if !angle.is_finite() {
+                continue;
+            }
+            let normal = normals[corner];
+            tangents[corner] += (tangent - normal * normal.dot(tangent)).normalize_or_zero() * angle;
+            bitangents[corner] += (bitangent - normal * normal.dot(bitangent)).normalize_or_zero() * angle;
+        }
+    }
+    tangents
+        .iter()
+        .zip(bitangents)
+        .zip(normals)
+        .map(|((&tangent, bitangent), &normal)| {
+            let mut tangent = (tangent - normal * normal.dot(tangent)).normalize_or_zero();
+            if tangent == Vec3::ZERO {
+                tangent = normal.try_normalize().map_or(Vec3::X, |normal| normal.any_orthonormal_vector());
+            }
+            let handedness = if normal.cross(tangent).dot(bitangent) < 0. { -1. } else { 1. };
+            tangent.extend(handedness)
+        })
+        .collect()
+}
+
+/// Give normals to the faces missing some
+///
+/// Faces in a smoothing group share area and angle weighted normals on their common positions,
+/// other faces get their flat `calculated` normal.
+/// The new normals are appended to `normals`
+pub(crate) fn generate_normals(
+    pos: &[Vec3],
+    calculated: &[Vec3],
+    normals: &mut Vec<Vec3>,
+    faces: &mut [Triangle],
+) {
+    let flat_offset = normals.len();
+    if faces.iter().any(|face| face.normals.is_none() && face.smoothing.is_none()) {
+        normals.extend(calculated.iter().map(|normal| normal.normalize_or_zero()));
+    }
+
+    let mut smooth: HashMap<(usize, u32), usize> = HashMap::new();
+    for face in faces.iter_mut().filter(|face| face.normals.is_none()) {
+        let Some(group) = face.smoothing else {
+            face.normals = Some([flat_offset + face.calculated_normal; 3]);
+            continue;
+        };
+        let corners = face.points.map(|point| pos[point]);
+        // the cross product length is twice the face area
+        let weighted = (corners[1] - corners[0]).cross(corners[2] - corners[0]);
+        let mut indices = [0; 3];
+        for i in 0..3 {
+            let angle = (corners[(i + 1) % 3] - corners[i]).angle_between(corners[(i + 2) % 3] - corners[i]);
+            let idx = *smooth.entry((face.points[i], group)).or_insert_with(|| {
+                normals.push(Vec3::ZERO);
+                normals.len() - 1
+            });
+            if angle.is_finite() {
+                normals[idx] += weighted * angle;
+            }
+            indices[i] = idx;
+        }
+        face.normals = Some(indices);
+    }
+    for idx in smooth.into_values() {
+        normals[idx] = normals[idx].normalize_or_zero();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mesh::Mesh;
+
+    #[test]
+    fn positions_within_epsilon_are_merged() {
+        let positions = [Vec3::ZERO, Vec3::new(0.05, 0., 0.), Vec3::X, Vec3::new(0., 0.15, 0.), Vec3::new(1., 0.09, 0.)];
+        assert_eq!(weld_positions(&positions, 0.1), [0, 0, 2, 3, 2]);
+    }
+
+    #[test]
+    fn positions_just_outside_epsilon_are_kept() {
+        let positions = [Vec3::ZERO, Vec3::new(0.1001, 0., 0.), Vec3::new(0., 0., -0.1001)];
+        assert_eq!(weld_positions(&positions, 0.1), [0, 1, 2]);
+    }
+
+    #[test]
+    fn positions_merge_into_the_first_one() {
+        // the middle position lies within epsilon of both others, which are too far apart to merge together
+        let positions = [Vec3::new(0.08, 0., 0.), Vec3::ZERO, Vec3::new(-0.08, 0., 0.)];
+        assert_eq!(weld_positions(&positions, 0.1), [0, 0, 2]);
+        assert_eq!(weld_positions(&[positions[1], positions[0], positions[2]], 0.1), [0, 0, 0]);
+    }
+
+    #[test]
+    fn invalid_epsilons_only_merge_identical_positions() {
+        let positions = [Vec3::ZERO, Vec3::new(1e-3, 0., 0.), Vec3::ZERO];
+        for epsilon in [0., -1., f32::NAN, f32::INFINITY] {
+            assert_eq!(weld_positions(&positions, epsilon), [0, 1, 0]);
+        }
+    }
+
+    #[test]
+    fn large_coordinates_are_welded() {
+        let positions = vec![Vec3::new(1e4, 0., 0.), Vec3::new(1e4, 1., 0.), Vec3::new(1e4, 0., 1.), Vec3::new(1e4, 0., 0.)];
+        let mut mesh = Mesh::from_buffers(vec![0, 1, 2, 3, 2, 1], positions, vec![], vec![Vec3::X; 4]).unwrap();
+        mesh.weld(1e-6).unwrap();
+        assert_eq!(mesh.vertices.len(), 3);
+        assert_eq!(mesh.faces, [0, 1, 2, 0, 2, 1]);
+
+        let far = [Vec3::splat(f32::MAX), Vec3::splat(f32::MIN), Vec3::splat(f32::MAX)];
+        assert_eq!(weld_positions(&far, 1e-6), [0, 1, 0]);
+    }
+
+    #[test]
+    fn meshes_reject_invalid_epsilons() {
+        let mut mesh = Mesh::from_buffers(vec![0, 1, 2], vec![Vec3::ZERO, Vec3::X, Vec3::Y], vec![], vec![]).unwrap();
+        for epsilon in [0., -1., f32::NAN, f32::INFINITY] {
+            assert!(mesh.weld(epsilon).is_err());
+        }
+        assert_eq!(mesh.vertices.len(), 3);
+    }
+
+    #[test]
+    fn tangents_follow_uvs() {
+        let positions = [Vec3::ZERO, Vec3::X, Vec3::Y];
+        let normals = [Vec3::Z; 3];
+        let tangents = generate_tangents(&positions, &positions, &normals, &[0, 1, 2]);
+        assert_eq!(tangents, vec![Vec4::new(1., 0., 0., 1.); 3]);
+
+        let mirrored = [Vec3::X, Vec3::ZERO, Vec3::new(1., 1., 0.)];
+        let tangents = generate_tangents(&positions, &mirrored, &normals, &[0, 1, 2]);
+        assert_eq!(tangents, vec![Vec4::new(-1., 0., 0., -1.); 3]);
+    }
+
+    #[test]
+    fn tangents_of_mismatched_buffers() {
+        let positions = [Vec3::ZERO, Vec3::X, Vec3::Y];
+        let tangents = generate_tangents(&positions, &[], &[Vec3::Z; 3], &[0, 1, 2]);
+        assert_eq!(tangents, vec![Vec4::new(1., 0., 0., 1.); 3]);
+        let tangents = generate_tangents(&positions, &positions, &[Vec3::Z], &[0, 1, 5]);
+        assert_eq!(tangents, vec![Vec4::new(1., 0., 0., 1.); 3]);
+    }
 }
@@ -0,0 +1,294 @@
+//! STL parser
+//!
+//! Loads both ASCII and binary stl files, the variant being detected from the file itself.
+//! Each facet keeps its normal as a flat normal, ASCII `solid` blocks become groups
+
+use std::io::BufRead;
+
+use crate::error::{MeshParseError, MeshParseErrorKind};
+use crate::Vec3;
+
+use super::obj_parser::{column, parse_floats, statement_argument, triangulate, LineResult};
+use super::solver::{solve_indices, unique_indices};
+use super::mtl_parser::MaterialLibrary;
+use super::{Aabb, BoundingSphere, Mesh, Triangle};
+
+/// size of the header of a binary stl, followed by the triangle count
+const HEADER_SIZE: usize = 80;
+/// normal, three corners and an attribute count
+const TRIANGLE_SIZE: usize = 50;
+
+pub(crate) struct STLMesh {
+    pub(crate) vertices: Vec<Vec3>,
+    pub(crate) normals: Vec<Vec3>,
+    pub(crate) triangles: Vec<Triangle>,
+    pub(crate) groups: Vec<String>,
+    current_group: Option<u32>,
+    current_normal: Option<Vec3>,
+    corners: Vec<Vec3>,
+}
+
+/// Binary files are recognized by their size matching their triangle count,
+/// as some exporters start their binary header with `solid` too
+fn is_binary(bytes: &[u8]) -> bool {
+    if bytes.len() >= HEADER_SIZE + 4 {
+        let count = u32::from_le_bytes([bytes[80], bytes[81], bytes[82], bytes[83]]) as usize;
+        if HEADER_SIZE + 4 + count * TRIANGLE_SIZE == bytes.len() {
+            return true;
+        }
+    }
+    !bytes.trim_ascii_start().starts_with(b"solid")
+}
+
+fn read_vec3(bytes: &[u8]) -> Vec3 {
+    let value = |i: usize| f32::from_le_bytes([bytes[i], bytes[i + 1], bytes[i + 2], bytes[i + 3]]);
+    Vec3::new(value(0), value(4), value(8))
+}
+
+impl STLMesh {
+    pub fn new() -> STLMesh {
+        STLMesh {
+            vertices: vec![],
+            normals: vec![],
+            triangles: vec![],
+            groups: vec![],
+            current_group: None,
+            current_normal: None,
+            corners: vec![],
+        }
+    }
+
+    pub fn load_stl(&mut self, file_name: &str) -> Result<(), MeshParseError> {
+        match std::fs::read(file_name) {
+            Ok(bytes) => self.load_stl_bytes(&bytes, file_name),
+            Err(why) => Err(MeshParseError::new(file_name, 0, 0, MeshParseErrorKind::Io(why))),
+        }
+    }
+
+    /// Parse ASCII or binary stl data, `file_name` is used in errors
+    pub fn load_stl_bytes(&mut self, bytes: &[u8], file_name: &str) -> Result<(), MeshParseError> {
+        if is_binary(bytes) {
+            self.load_binary(bytes, file_name)
+        } else {
+            self.load_ascii(bytes, file_name)
+        }
+    }
+
+    fn load_binary(&mut self, bytes: &[u8], file_name: &str) -> Result<(), MeshParseError> {
+        let count = match bytes.get(HEADER_SIZE..HEADER_SIZE + 4) {
+            Some(count) => u32::from_le_bytes([count[0], count[1], count[2], count[3]]) as usize,
+            None => return Err(MeshParseError::new(file_name, 0, 0, MeshParseErrorKind::MissingValue)),
+        };
+        let triangles = &bytes[HEADER_SIZE + 4..];
+        if triangles.len() < count * TRIANGLE_SIZE {
+            return Err(MeshParseError::new(file_name, 0, 0, MeshParseErrorKind::MissingValue));
+        }
+        for triangle in triangles.chunks_exact(TRIANGLE_SIZE).take(count) {
+            let corners = [read_vec3(&triangle[12..]), read_vec3(&triangle[24..]), read_vec3(&triangle[36..])];
+            self.add_facet(&corners, read_vec3(triangle));
+        }
+        Ok(())
+    }
+
+    fn load_ascii(&mut self, bytes: &[u8], file_name: &str) -> Result<(), MeshParseError> {
+        for (line_idx, option_line) in bytes.lines().enumerate() {
+            let line = option_line.map_err(|why| {
+                MeshParseError::new(file_name, line_idx + 1, 0, MeshParseErrorKind::Io(why))
+            })?;
+            self.parse_line(&line).map_err(|(column, kind)| {
+                MeshParseError::new(file_name, line_idx + 1, column, kind)
+            })?;
+        }
+        Ok(())
+    }
+
+    fn parse_line(&mut self, line: &str) -> LineResult<()> {
+        let statement = line.trim();
+        let keyword = statement.split_ascii_whitespace().next().unwrap_or("");
+        match keyword {
+            "" => {}
+            "solid" => {
+                self.groups.push(statement["solid".len()..].trim().into());
+                self.current_group = Some(self.groups.len() as u32 - 1);
+            }
+            "facet" => {
+                let normal = statement_argument(line, statement)?;
+                self.current_normal = Some(parse_floats(line, normal, 3, 3)?);
+                self.corners.clear();
+            }
+            "vertex" => {
+                self.corners.push(parse_floats(line, statement, 3, 3)?);
+            }
+            "endfacet" => {
+                if self.corners.len() < 3 {
+                    return Err((column(line, statement), MeshParseErrorKind::MissingValue));
+                }
+                let corners = std::mem::take(&mut self.corners);
+                self.add_facet(&corners, self.current_normal.unwrap_or(Vec3::ZERO));
+            }
+            "outer" | "endloop" | "endsolid" => {}
+            _ => return Err((column(line, keyword), MeshParseErrorKind::UnsupportedStatement)),
+        }
+        Ok(())
+    }
+
+    /// Add a facet with its flat normal, computed from its corners when left to zero
+    fn add_facet(&mut self, corners: &[Vec3], normal: Vec3) {
+        let normal = match normal.normalize_or_zero() {
+            Vec3::ZERO => (corners[1] - corners[0]).cross(corners[2] - corners[0]).normalize_or_zero(),
+            normal => normal,
+        };
+        let normal_idx = self.normals.len();
+        self.normals.push(normal);
+        let start = self.vertices.len();
+        self.vertices.extend(corners);
+        for [a, b, c] in triangulate(corners) {
+            let mut triangle = Triangle::new();
+            triangle.points = [start + a, start + b, start + c];
+            triangle.normals = Some([normal_idx; 3]);
+            triangle.textures = None;
+            triangle.group = self.current_group;
+            self.triangles.push(triangle);
+        }
+    }
+}
+
+impl Mesh {
+    /// Load a mesh from an ASCII or binary .stl file
+    ///
+    /// facets are flat shaded, their corners sharing a position and a normal are merged
+    pub fn from_stl(file_name: &str) -> Result<Mesh, MeshParseError> {
+        let mut stl = STLMesh::new();
+        stl.load_stl(file_name)?;
+        Ok(Self::from_stl_mesh(stl, file_name))
+    }
+
+    /// Load a mesh from ASCII or binary stl data in memory, see [`Self::from_stl()`]
+    pub fn from_stl_bytes(bytes: &[u8], name: &str) -> Result<Mesh, MeshParseError> {
+        let mut stl = STLMesh::new();
+        stl.load_stl_bytes(bytes, name)?;
+        Ok(Self::from_stl_mesh(stl, name))
+    }
+
+    fn from_stl_mesh(mut stl: STLMesh, path: &str) -> Mesh {
+        // facets sharing a plane share their normal, so that their common corners can be merged
+        let normals = unique_indices(&stl.normals);
+        for triangle in &mut stl.triangles {
+            triangle.normals = triangle.normals.map(|corners| corners.map(|corner| normals[corner]));
+        }
+        let (vertices, uvs, normals, faces, _) =
+            solve_indices(&stl.vertices, &[], &stl.normals, &stl.triangles, Some(0.));
+        let mut groups: Vec<(u32, String)> = vec![];
+        for (i, triangle) in stl.triangles.iter().enumerate() {
+            if let Some(group) = triangle.group {
+                if groups.last().is_none_or(|(_, last)| *last != stl.groups[group as usize]) {
+                    groups.push(((i * 3) as u32, stl.groups[group as usize].clone()));
+                }
+            }
+        }
+        let (aabb, bounding_sphere) = (Aabb::from_points(&vertices), BoundingSphere::from_points(&vertices));
+        Mesh {
+            path: path.into(),
+            faces: faces.iter().map(|x| *x as u32).collect(),
+            vertices,
+            uvs,
+            normals,
+            tangents: vec![],
+            colors: vec![],
+            point_cloud: false,
+            aabb,
+            bounding_sphere,
+            materials: vec![],
+            material_sources: MaterialLibrary::new(),
+            groups,
+            weights: vec![],
+            bones: vec![],
+            dependencies: vec![],
+            warnings: vec![],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mesh::StlFormat;
+
+    const ASCII: &str = "solid plate
+  facet normal 0 0 1
+    outer loop
+      vertex 0 0 0
+      vertex 1 0 0
+      vertex 1 1 0
+    endloop
+  endfacet
+  facet normal 0 0 1
+    outer loop
+      vertex 0 0 0
+      vertex 1 1 0
+      vertex 0 1 0
+    endloop
+  endfacet
+endsolid plate
+";
+
+    /// binary stl data holding `triangles`, their normals left to zero
+    fn binary(header: &str, triangles: &[[Vec3; 3]]) -> Vec<u8> {
+        let mut bytes = header.as_bytes().to_vec();
+        bytes.resize(HEADER_SIZE, 0);
+        bytes.extend((triangles.len() as u32).to_le_bytes());
+        for corners in triangles {
+            for vector in std::iter::once(&Vec3::ZERO).chain(corners) {
+                bytes.extend(vector.to_array().iter().flat_map(|value| value.to_le_bytes()));
+            }
+            bytes.extend(0u16.to_le_bytes());
+        }
+        bytes
+    }
+
+    #[test]
+    fn ascii_files_are_detected() {
+        assert!(!is_binary(ASCII.as_bytes()));
+        let mesh = Mesh::from_stl_bytes(ASCII.as_bytes(), "plate.stl").unwrap();
+        assert_eq!(mesh.faces.len(), 6);
+        // both facets share their plane, so their common corners are merged
+        assert_eq!(mesh.vertices.len(), 4);
+        assert!(mesh.normals.iter().all(|normal| *normal == Vec3::Z));
+        assert_eq!(mesh.groups, vec![(0, "plate".to_string())]);
+    }
+
+    #[test]
+    fn binary_files_are_detected_by_their_size() {
+        let triangle = [Vec3::ZERO, Vec3::X, Vec3::Y];
+        for header in ["exported by some tool", "solid exported by some tool"] {
+            let bytes = binary(header, &[triangle]);
+            assert!(is_binary(&bytes), "{header}");
+            let mesh = Mesh::from_stl_bytes(&bytes, "triangle.stl").unwrap();
+            assert_eq!(mesh.faces.len(), 3);
+            assert_eq!(mesh.vertices, triangle);
+            // zero normals are computed from the corners
+            assert_eq!(mesh.normals, vec![Vec3::Z; 3]);
+        }
+
+        let mut truncated = binary("exported by some tool", &[triangle, triangle]);
+        truncated.truncate(truncated.len() - 1);
+        let error = match Mesh::from_stl_bytes(&truncated, "truncated.stl") {
+            Ok(_) => panic!("truncated stl data loaded"),
+            Err(why) => why,
+        };
+        assert!(matches!(error.kind, MeshParseErrorKind::MissingValue));
+    }
+
+    #[test]
+    fn written_files_load_back() {
+        let mesh = Mesh::from_stl_bytes(ASCII.as_bytes(), "plate.stl").unwrap();
+        for format in [StlFormat::Ascii, StlFormat::Binary] {
+            let mut bytes = vec![];
+            mesh.write_stl(&mut bytes, format).unwrap();
+            assert_eq!(is_binary(&bytes), format == StlFormat::Binary);
+            let loaded = Mesh::from_stl_bytes(&bytes, "plate.stl").unwrap();
+            assert_eq!(loaded.faces, mesh.faces);
+            assert_eq!(loaded.vertices, mesh.vertices);
+        }
+    }
+}
@@ -0,0 +1,70 @@
+//! STL exporter
+//!
+//! Writes the faces of a [`Mesh`] as an ASCII or binary stl file
+
+use std::io::Write;
+use std::path::Path;
+
+use crate::Vec3;
+
+use super::Mesh;
+
+/// Variant of the stl format to write
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StlFormat {
+    Ascii,
+    Binary,
+}
+
+impl Mesh {
+    /// Write the mesh as stl data
+    ///
+    /// Each triangle is written with its flat normal, uvs, vertex normals, groups and materials are lost.
+    /// Point clouds have no facet to write
+    pub fn write_stl<W: Write>(&self, writer: &mut W, format: StlFormat) -> std::io::Result<()> {
+        let name = Path::new(&self.path)
+            .file_stem()
+            .map_or(String::new(), |stem| stem.to_string_lossy().replace(char::is_whitespace, "_"));
+        let faces: &[u32] = if self.point_cloud { &[] } else { &self.faces };
+        let triangles: Vec<(Vec3, [Vec3; 3])> = faces
+            .chunks_exact(3)
+            .map(|face| {
+                let corners = [0, 1, 2].map(|i| self.vertices[face[i] as usize]);
+                let normal = (corners[1] - corners[0]).cross(corners[2] - corners[0]).normalize_or_zero();
+                (normal, corners)
+            })
+            .collect();
+
+        match format {
+            StlFormat::Ascii => {
+                writeln!(writer, "solid {}", name)?;
+                for (normal, corners) in &triangles {
+                    writeln!(writer, "  facet normal {} {} {}", normal.x, normal.y, normal.z)?;
+                    writeln!(writer, "    outer loop")?;
+                    for corner in corners {
+                        writeln!(writer, "      vertex {} {} {}", corner.x, corner.y, corner.z)?;
+                    }
+                    writeln!(writer, "    endloop")?;
+                    writeln!(writer, "  endfacet")?;
+                }
+                writeln!(writer, "endsolid {}", name)
+            }
+            StlFormat::Binary => {
+                // the header must not start with `solid`, or readers may take the file for ASCII
+                let mut header = format!("binary stl {}", name).into_bytes();
+                header.resize(80, 0);
+                writer.write_all(&header)?;
+                writer.write_all(&(triangles.len() as u32).to_le_bytes())?;
+                for (normal, corners) in &triangles {
+                    for vector in std::iter::once(normal).chain(corners) {
+                        for value in vector.to_array() {
+                            writer.write_all(&value.to_le_bytes())?;
+                        }
+                    }
+                    writer.write_all(&0u16.to_le_bytes())?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
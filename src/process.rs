@@ -1,12 +1,13 @@
 use crate::app::{matrices_as_bytes_copy, vertices_as_bytes_copy, App};
 use crate::camera::Camera;
+use crate::frustum::CullingStats;
 use crate::graphics::Graphics;
 use crate::uniforms::Uniforms;
-use crate::graphics::MaterialSlot;
+use crate::mesh::MeshPart;
 
 use std::cell::RefMut;
 
-use glam::Mat4;
+use glam::{Mat4, Vec3};
 
 use nannou::event::Update;
 use nannou::wgpu;
@@ -50,7 +51,9 @@ fn three_d_view_rendering(mut graphics: RefMut<Graphics>, frame: &Frame, camera:
     }
 
     // Update the uniforms
-    let uniform_buffer = Uniforms::new_as_buffer_view(frame_size.into(), camera, device);
+    let uniforms = Uniforms::new(frame_size.into(), camera.calc_view_matrix(), camera.fov);
+    let uniform_buffer = uniforms.as_buffer_view(device);
+    let frustum = uniforms.frustum();
     let uniforms_size = std::mem::size_of::<Uniforms>() as wgpu::BufferAddress;
 
     let mut encoder = frame.command_encoder();
@@ -63,19 +66,37 @@ fn three_d_view_rendering(mut graphics: RefMut<Graphics>, frame: &Frame, camera:
     );
 
     let mut buffers: Vec<wgpu::Buffer> = vec![];
-    let mut materials: Vec<MaterialSlot> = vec![];
+    let mut parts: Vec<Vec<MeshPart>> = vec![];
+    let mut index_formats: Vec<wgpu::IndexFormat> = vec![];
+    let mut topologies: Vec<wgpu::PrimitiveTopology> = vec![];
     let mut instance_buffers: Vec<wgpu::Buffer> = vec![];
     let mut inst_color_buffers: Vec<wgpu::Buffer> = vec![];
-    let mut counts: Vec<usize> = vec![];
     let mut all_instances: Vec<Vec<Mat4>> = vec![]; //= vec![Mat4::from_rotation_x(std::f32::consts::PI * 0.5), Mat4::from_translation(Vec3::new(2., 0., 0.))];
 
+    let mut culling_stats = CullingStats::default();
     for (md, (colors, instances)) in &graphics.draw_queue {
         if let Some(mesh) = graphics.meshes.get(&md.idx) {
-            graphics.draw(device, &mut buffers, &mut counts, mesh);
-            materials.push(md.material);
+            culling_stats.queued += instances.len() as u32;
+            // only the instances whose world bounds reach the frustum are uploaded
+            let (colors, instances): (Vec<Vec3>, Vec<Mat4>) = graphics
+                .instance_bounds(md)
+                .iter()
+                .zip(colors.iter().zip(instances))
+                .filter(|((aabb, sphere), _)| frustum.intersects_sphere(sphere) && frustum.intersects_aabb(aabb))
+                .map(|(_, (color, instance))| (*color, *instance))
+                .unzip();
+            if instances.is_empty() {
+                culling_stats.skipped_meshes += 1;
+                continue;
+            }
+            culling_stats.visible += instances.len() as u32;
+            graphics.draw(device, &mut buffers, mesh);
+            parts.push(md.parts(mesh.faces.len()));
+            index_formats.push(mesh.index_format());
+            topologies.push(mesh.topology());
             all_instances.push(instances.clone());
-            let raw_instance_col = vertices_as_bytes_copy(colors);
-            let raw_instance_mat = matrices_as_bytes_copy(instances);
+            let raw_instance_col = vertices_as_bytes_copy(&colors);
+            let raw_instance_mat = matrices_as_bytes_copy(&instances);
             inst_color_buffers.push(device.create_buffer_init(&wgpu::BufferInitDescriptor {
                 label: None,
                 contents: &*raw_instance_col,
@@ -89,6 +110,29 @@ fn three_d_view_rendering(mut graphics: RefMut<Graphics>, frame: &Frame, camera:
         }
     }
     graphics.draw_queue.clear();
+    graphics.culling_stats = culling_stats;
+
+    // skinned meshes are drawn one at a time, each with its own bone matrices
+    let mut skinned_buffers: Vec<wgpu::Buffer> = vec![];
+    let mut skinned_draws = vec![];
+    for (md, color, transform, pose) in std::mem::take(&mut graphics.skinned_queue) {
+        if let Some(mesh) = graphics.meshes.get(&md.idx) {
+            graphics.draw(device, &mut skinned_buffers, mesh);
+            skinned_buffers.push(device.create_buffer_init(&wgpu::BufferInitDescriptor {
+                label: None,
+                contents: &vertices_as_bytes_copy(&vec![color]),
+                usage: wgpu::BufferUsages::VERTEX,
+            }));
+            skinned_buffers.push(device.create_buffer_init(&wgpu::BufferInitDescriptor {
+                label: None,
+                contents: &matrices_as_bytes_copy(&vec![transform]),
+                usage: wgpu::BufferUsages::VERTEX,
+            }));
+            graphics.draw_skin(device, &mut skinned_buffers, mesh);
+            let (bone_buffer, bone_group) = graphics.bone_bind_group(device, &pose);
+            skinned_draws.push((md.parts(mesh.faces.len()), mesh.index_format(), bone_buffer, bone_group));
+        }
+    }
     {
         let mut render_pass = wgpu::RenderPassBuilder::new()
             .color_attachment(frame.texture_view(), |color| color)
@@ -97,34 +141,63 @@ fn three_d_view_rendering(mut graphics: RefMut<Graphics>, frame: &Frame, camera:
             .begin(&mut encoder);
         render_pass.set_bind_group(0, &graphics.uniform_bind_group, &[]);
 
-        let mut count = counts.iter();
-        let mut material = materials.iter();
+        let mut mesh_parts = parts.iter();
+        let mut index_format = index_formats.iter();
+        let mut topology = topologies.iter();
         let mut instance = all_instances.iter();
         let mut instance_buffer = instance_buffers.iter();
         let mut instance_color = inst_color_buffers.iter();
-        for i in (0..buffers.len()).step_by(4) {
+        for i in (0..buffers.len()).step_by(6) {
             if let (
-                Some(c),
                 Some(inst),
                 Some(inst_buff),
                 Some(inst_color),
-                Some(mat)
+                Some(mesh_parts),
+                Some(format),
+                Some(topology)
             ) = (
-                count.next(),
                 instance.next(),
                 instance_buffer.next(),
                 instance_color.next(),
-                material.next().and_then(|mat_desc|graphics.materials.get(mat_desc)),
+                mesh_parts.next(),
+                index_format.next(),
+                topology.next(),
             ) {
-                render_pass.set_bind_group(1, &mat.group, &[]);
-                render_pass.set_pipeline(&graphics.render_pipelines[&mat.shader]);
-                render_pass.set_index_buffer(buffers[i].slice(..), wgpu::IndexFormat::Uint16);
+                let pipelines = match topology {
+                    wgpu::PrimitiveTopology::PointList => &graphics.point_pipelines,
+                    _ => &graphics.render_pipelines,
+                };
+                render_pass.set_index_buffer(buffers[i].slice(..), *format);
                 render_pass.set_vertex_buffer(0, buffers[i + 1].slice(..));
                 render_pass.set_vertex_buffer(1, buffers[i + 2].slice(..));
                 render_pass.set_vertex_buffer(2, buffers[i + 3].slice(..));
-                render_pass.set_vertex_buffer(3, inst_color.slice(..));
-                render_pass.set_vertex_buffer(4, inst_buff.slice(..));
-                render_pass.draw_indexed(0..*c as u32, 0, 0..inst.len() as u32);
+                render_pass.set_vertex_buffer(3, buffers[i + 4].slice(..));
+                render_pass.set_vertex_buffer(4, buffers[i + 5].slice(..));
+                render_pass.set_vertex_buffer(5, inst_color.slice(..));
+                render_pass.set_vertex_buffer(6, inst_buff.slice(..));
+                for part in mesh_parts {
+                    if let Some(mat) = graphics.materials.get(&part.material) {
+                        render_pass.set_bind_group(1, &mat.group, &[]);
+                        render_pass.set_pipeline(&pipelines[&mat.shader]);
+                        render_pass.draw_indexed(part.start..part.end, 0, 0..inst.len() as u32);
+                    }
+                }
+            }
+        }
+
+        for (i, (mesh_parts, format, _, bone_group)) in skinned_draws.iter().enumerate() {
+            let buffers = &skinned_buffers[i * 9..(i + 1) * 9];
+            render_pass.set_index_buffer(buffers[0].slice(..), *format);
+            for (slot, buffer) in buffers[1..].iter().enumerate() {
+                render_pass.set_vertex_buffer(slot as u32, buffer.slice(..));
+            }
+            render_pass.set_bind_group(2, bone_group, &[]);
+            for part in mesh_parts {
+                if let Some(mat) = graphics.materials.get(&part.material) {
+                    render_pass.set_bind_group(1, &mat.group, &[]);
+                    render_pass.set_pipeline(&graphics.skinning.render_pipelines[&mat.shader]);
+                    render_pass.draw_indexed(part.start..part.end, 0, 0..1);
+                }
             }
         }
     }
@@ -11,6 +11,10 @@ struct Texel {
     [[location(1)]] uv: vec3<f32>;
     [[location(2)]] normal: vec3<f32>;
     [[location(3)]] color: vec3<f32>;
+    // vertex color, white when the mesh has none
+    [[location(4)]] vertex_color: vec4<f32>;
+    // tangent in view space, `w` giving the handedness of the bitangent for normal mapping
+    [[location(5)]] tangent: vec4<f32>;
 };
 
 [[block]]
@@ -40,5 +44,5 @@ fn main(tx: Texel) -> [[location(0)]] vec4<f32> {
 //    let out_color = vec4<f32>(mix(vec3<f32>(0.), clamp(color, vec3<f32>(0.), vec3<f32>(1.)), vec3<f32>(brightness)), 1.0);
 //    return out_color;
 //    return vec4<f32>(tx.normal.xyz, 1.);
-    return vec4<f32>((tx.normal.z * 0.5 + 0.5) * tx.color, 1.0);
+    return vec4<f32>((tx.normal.z * 0.5 + 0.5) * tx.color * tx.vertex_color.rgb, tx.vertex_color.a);
 }
@@ -19,6 +19,8 @@ struct Vertex {
     [[location(1)]] uv: vec3<f32>;
     [[location(2)]] normal: vec3<f32>;
     [[location(3)]] color: vec3<f32>;
+    [[location(4)]] vertex_color: vec4<f32>;
+    [[location(5)]] tangent: vec4<f32>;
 };
 
 [[group(0), binding(0)]]
@@ -45,6 +47,8 @@ fn main(
     [[location(0)]] pos: vec3<f32>,
     [[location(1)]] uv: vec3<f32>,
     [[location(2)]] normal: vec3<f32>,
+    [[location(3)]] vertex_color: vec4<f32>,
+    [[location(4)]] tangent: vec4<f32>,
     instance: InstanceInput,
 ) -> Vertex {
     let model_matrix = mat4x4<f32>(
@@ -58,7 +62,8 @@ fn main(
     let wv3: mat3x3<f32> = (mat3x3<f32>(worldview[0].xyz, worldview[1].xyz, worldview[2].xyz));
 //    let out_normal: vec3<f32> = wv3 * normal;
 //    let out_normal: vec3<f32> = transpose(custom_inverse(wv3)) * normal;
+    let out_tangent: vec4<f32> = vec4<f32>(normalize(wv3 * tangent.xyz), tangent.w);
     let out_pos: vec4<f32> = world * vec4<f32>(pos, 1.0);
     let v_pos: vec4<f32> = uniforms.proj * worldview * vec4<f32>(pos, 1.0);
-    return Vertex(v_pos, out_pos, uv, normalize(wv3 * normal), instance.color);
+    return Vertex(v_pos, out_pos, uv, normalize(wv3 * normal), instance.color, vertex_color, out_tangent);
 }
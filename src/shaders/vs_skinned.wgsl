@@ -0,0 +1,76 @@
+[[block]]
+struct Data {
+    world: mat4x4<f32>;
+    view: mat4x4<f32>;
+    proj: mat4x4<f32>;
+};
+
+struct InstanceInput {
+    [[location(5)]] model_matrix_0: vec4<f32>;
+    [[location(6)]] model_matrix_1: vec4<f32>;
+    [[location(7)]] model_matrix_2: vec4<f32>;
+    [[location(8)]] model_matrix_3: vec4<f32>;
+    [[location(9)]] color: vec3<f32>;
+};
+
+struct SkinInput {
+    [[location(10)]] joints: vec4<u32>;
+    [[location(11)]] weights: vec4<f32>;
+};
+
+struct Vertex {
+    [[builtin(position)]] vpos: vec4<f32>;
+    [[location(0)]] pos: vec4<f32>;
+    [[location(1)]] uv: vec3<f32>;
+    [[location(2)]] normal: vec3<f32>;
+    [[location(3)]] color: vec3<f32>;
+    [[location(4)]] vertex_color: vec4<f32>;
+    [[location(5)]] tangent: vec4<f32>;
+};
+
+[[block]]
+struct Bones {
+    matrices: array<mat4x4<f32>>;
+};
+
+[[group(0), binding(0)]]
+var<uniform> uniforms: Data;
+
+[[group(2), binding(0)]]
+var<storage, read> bones: Bones;
+
+// blend a vector by the bone matrices influencing the vertex
+fn skin(input: SkinInput, v: vec4<f32>) -> vec4<f32> {
+    return bones.matrices[input.joints.x] * v * input.weights.x
+         + bones.matrices[input.joints.y] * v * input.weights.y
+         + bones.matrices[input.joints.z] * v * input.weights.z
+         + bones.matrices[input.joints.w] * v * input.weights.w;
+}
+
+[[stage(vertex)]]
+fn main(
+    [[location(0)]] pos: vec3<f32>,
+    [[location(1)]] uv: vec3<f32>,
+    [[location(2)]] normal: vec3<f32>,
+    [[location(3)]] vertex_color: vec4<f32>,
+    [[location(4)]] tangent: vec4<f32>,
+    instance: InstanceInput,
+    skin_input: SkinInput,
+) -> Vertex {
+    let skinned_pos: vec4<f32> = skin(skin_input, vec4<f32>(pos, 1.0));
+    let skinned_normal: vec3<f32> = skin(skin_input, vec4<f32>(normal, 0.0)).xyz;
+    let skinned_tangent: vec3<f32> = skin(skin_input, vec4<f32>(tangent.xyz, 0.0)).xyz;
+    let model_matrix = mat4x4<f32>(
+            instance.model_matrix_0,
+            instance.model_matrix_1,
+            instance.model_matrix_2,
+            instance.model_matrix_3,
+        );
+    let world: mat4x4<f32> = uniforms.world * model_matrix;
+    let worldview: mat4x4<f32> = uniforms.view * world;
+    let wv3: mat3x3<f32> = (mat3x3<f32>(worldview[0].xyz, worldview[1].xyz, worldview[2].xyz));
+    let out_tangent: vec4<f32> = vec4<f32>(normalize(wv3 * skinned_tangent), tangent.w);
+    let out_pos: vec4<f32> = world * skinned_pos;
+    let v_pos: vec4<f32> = uniforms.proj * worldview * skinned_pos;
+    return Vertex(v_pos, out_pos, uv, normalize(wv3 * skinned_normal), instance.color, vertex_color, out_tangent);
+}
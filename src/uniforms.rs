@@ -5,6 +5,7 @@ use nannou::wgpu;
 use nannou::wgpu::util::DeviceExt;
 
 use crate::camera::Camera;
+use crate::frustum::Frustum;
 
 #[repr(C)]
 #[derive(Copy, Clone, Debug)]
@@ -43,13 +44,9 @@ impl Uniforms {
         })
     }
 
-    pub(crate) fn new_as_buffer_view(
-        window_size: glam::UVec2,
-        camera: &Camera,
-        device: &nannou::wgpu::Device,
-    ) -> wgpu::Buffer {
-        let uniforms = Uniforms::new(window_size, camera.calc_view_matrix().into(), camera.fov);
-        let uniforms_bytes = uniforms.as_bytes_copy();
+    /// a buffer to copy the uniforms from, into the one bound for rendering
+    pub(crate) fn as_buffer_view(&self, device: &nannou::wgpu::Device) -> wgpu::Buffer {
+        let uniforms_bytes = self.as_bytes_copy();
         let usage = wgpu::BufferUsages::COPY_SRC;
 
         device.create_buffer_init(&wgpu::BufferInitDescriptor {
@@ -73,4 +70,9 @@ impl Uniforms {
             proj: proj.into(),
         }
     }
+
+    /// The volume seen through these matrices, in the space of the instance transforms
+    pub fn frustum(&self) -> Frustum {
+        Frustum::from_matrix(self.proj * self.view * self.world)
+    }
 }